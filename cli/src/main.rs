@@ -0,0 +1,285 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+use std::process::ExitCode;
+
+use rustfu_renderer::decode::{DecodeOptions, Decoder, StringEncoding};
+use rustfu_renderer::diff::AnimationDiff;
+use rustfu_renderer::encode::Encode;
+use rustfu_renderer::types::Animation;
+use rustfu_renderer::{atlas, godot, import, spine, text};
+
+const USAGE: &str = "usage: rustfu [--lenient] [--encoding <strict|lossy|latin1>] <command>
+
+options:
+  --lenient                 skip over unknown actions, sprite and frame data tags
+  --encoding <encoding>     how to decode strings that are not valid UTF-8 (default: strict)
+
+commands:
+  rustfu diff <old.anm> <new.anm>                   compare two animations
+  rustfu convert <input> <output>                   convert between .anm, .ron and .json
+  rustfu spine <file.anm> <atlas.png> <out.json>    export to Spine JSON and atlas
+  rustfu godot <file.anm> <atlas.png> <out.tscn>    export to a Godot scene
+  rustfu slice <file.anm> <atlas.png> <out dir>     extract every shape as a PNG
+  rustfu repack [--no-trim] <out.png> (<file.anm> <parts dir> <out.anm>)...
+                                                    pack sliced parts into a new atlas
+  rustfu import <frames dir> <out.anm> <out.png>    build an animation from PNG frames
+  rustfu import <sheet.png> <sheet.json | CxR> <out.anm> <out.png>
+                                                    build an animation from a sprite sheet";
+
+const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// Exit status of `diff` when the animations differ. Like `diff(1)`, errors exit with 2.
+const EXIT_DIFFERENT: u8 = 1;
+const EXIT_ERROR: u8 = 2;
+
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    match run(&args) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
+
+fn run(args: &[String]) -> anyhow::Result<ExitCode> {
+    let (options, args) = parse_decode_options(args)?;
+    match args {
+        [cmd, old, new] if cmd == "diff" => {
+            let diff = AnimationDiff::compute(
+                &load_animation(old, options)?,
+                &load_animation(new, options)?,
+            );
+            print!("{}", diff);
+            if diff.is_empty() {
+                Ok(ExitCode::SUCCESS)
+            } else {
+                Ok(ExitCode::from(EXIT_DIFFERENT))
+            }
+        }
+        [cmd, input, output] if cmd == "convert" => {
+            let animation = read_converted(input, options)?;
+            write_converted(output, &animation)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        [cmd, input, atlas, output] if cmd == "spine" => {
+            let animation = load_animation(input, options)?;
+            let output = Path::new(output);
+            let (width, height) = image::image_dimensions(atlas)?;
+            let image_name = file_name(atlas)?;
+
+            let skeleton = spine::to_json(&animation, "./");
+            std::fs::write(output, serde_json::to_string_pretty(&skeleton)?)?;
+            let atlas = spine::to_atlas(&animation, image_name, width, height);
+            std::fs::write(output.with_extension("atlas"), atlas)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        [cmd, input, atlas, output] if cmd == "godot" => {
+            let animation = load_animation(input, options)?;
+            let (width, height) = image::image_dimensions(atlas)?;
+            let atlas_path = format!("res://{}", file_name(atlas)?);
+            let scene = godot::scene(&animation, &atlas_path, width, height);
+            std::fs::write(output, scene)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        [cmd, input, atlas, output] if cmd == "slice" => {
+            let animation = load_animation(input, options)?;
+            let output = Path::new(output);
+            let atlas = image::open(atlas)?.to_rgba8();
+            let (parts, manifest) = atlas::slice(&animation, &atlas);
+
+            std::fs::create_dir_all(output)?;
+            for part in parts {
+                part.image
+                    .save(output.join(atlas::part_file_name(part.id)))?;
+            }
+            let manifest = serde_json::to_string_pretty(&manifest)?;
+            std::fs::write(output.join(MANIFEST_FILE_NAME), manifest)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        [cmd, rest @ ..] if cmd == "repack" => {
+            let (trim, rest) = match rest {
+                [flag, rest @ ..] if flag == "--no-trim" => (false, rest),
+                _ => (true, rest),
+            };
+            let [output, inputs @ ..] = rest else {
+                anyhow::bail!("invalid arguments\n{}", USAGE);
+            };
+            if inputs.is_empty() || inputs.len() % 3 != 0 {
+                anyhow::bail!("invalid arguments\n{}", USAGE);
+            }
+
+            let mut animations = inputs
+                .chunks(3)
+                .map(|input| load_animation(&input[0], options))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let mut sources = vec![];
+            for (animation, input) in animations.iter_mut().zip(inputs.chunks(3)) {
+                sources.push(atlas::PackSource {
+                    animation,
+                    parts: load_parts(&input[1])?,
+                });
+            }
+            let options = atlas::PackOptions {
+                trim,
+                ..atlas::PackOptions::default()
+            };
+            // the atlas is named after its file, like the atlases of the game
+            let texture = Path::new(output)
+                .file_stem()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let image = atlas::repack(&mut sources, &texture, &options)?;
+            image.save(output)?;
+
+            for (animation, input) in animations.iter().zip(inputs.chunks(3)) {
+                save_animation(&input[2], animation)?;
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        [cmd, input, output, atlas] if cmd == "import" => {
+            let mut paths = std::fs::read_dir(input)?
+                .map(|entry| Ok(entry?.path()))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            paths.retain(|path| path.extension().is_some_and(|ext| ext == "png"));
+            paths.sort_by_key(|path| import::natural_key(&path.to_string_lossy()));
+            let frames = paths
+                .iter()
+                .map(|path| Ok(image::open(path)?.to_rgba8()))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            write_import(&frames, output, atlas)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        [cmd, sheet, layout, output, atlas] if cmd == "import" => {
+            let sheet = image::open(sheet)?.to_rgba8();
+            let frames = if let Some((columns, rows)) = parse_grid(layout) {
+                import::split_grid(&sheet, columns, rows)
+            } else {
+                import::split_descriptor(&sheet, &std::fs::read_to_string(layout)?)?
+            };
+            write_import(&frames, output, atlas)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        _ => anyhow::bail!("invalid arguments\n{}", USAGE),
+    }
+}
+
+/// Parses a `COLUMNSxROWS` grid, returning `None` for anything else, like descriptor paths.
+fn parse_grid(layout: &str) -> Option<(u32, u32)> {
+    let (columns, rows) = layout.split_once('x')?;
+    let digits = |value: &str| !value.is_empty() && value.bytes().all(|byte| byte.is_ascii_digit());
+    if !digits(columns) || !digits(rows) {
+        return None;
+    }
+    Some((columns.parse().ok()?, rows.parse().ok()?))
+}
+
+/// Parses the decoding options preceding the command, returning the remaining arguments.
+fn parse_decode_options(mut args: &[String]) -> anyhow::Result<(DecodeOptions, &[String])> {
+    let mut options = DecodeOptions::default();
+    loop {
+        match args {
+            [flag, rest @ ..] if flag == "--lenient" => {
+                options.lenient = true;
+                args = rest;
+            }
+            [flag, encoding, rest @ ..] if flag == "--encoding" => {
+                options.strings = match encoding.as_str() {
+                    "strict" => StringEncoding::Strict,
+                    "lossy" => StringEncoding::Lossy,
+                    "latin1" => StringEncoding::Latin1,
+                    _ => anyhow::bail!("invalid encoding: {}\n{}", encoding, USAGE),
+                };
+                args = rest;
+            }
+            _ => return Ok((options, args)),
+        }
+    }
+}
+
+fn load_animation(path: impl AsRef<Path>, options: DecodeOptions) -> anyhow::Result<Animation> {
+    let mut reader = BufReader::new(File::open(&path)?);
+    let mut decoder = Decoder::new(options);
+    let animation = decoder.decode_animation(&mut reader)?;
+    for warning in decoder.warnings() {
+        eprintln!("warning: {}: {}", path.as_ref().display(), warning);
+    }
+    Ok(animation)
+}
+
+fn save_animation(path: impl AsRef<Path>, animation: &Animation) -> anyhow::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    animation.encode(&mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads an animation as `.anm`, `.ron` or `.json`, depending on the extension of the path.
+fn read_converted(path: &str, options: DecodeOptions) -> anyhow::Result<Animation> {
+    match extension(path) {
+        "anm" => load_animation(path, options),
+        "ron" => Ok(text::from_ron(&std::fs::read_to_string(path)?)?),
+        "json" => Ok(text::from_json(&std::fs::read_to_string(path)?)?),
+        _ => anyhow::bail!("unsupported format: {}\n{}", path, USAGE),
+    }
+}
+
+/// Writes an animation as `.anm`, `.ron` or `.json`, depending on the extension of the path.
+fn write_converted(path: &str, animation: &Animation) -> anyhow::Result<()> {
+    match extension(path) {
+        "anm" => save_animation(path, animation),
+        "ron" => Ok(std::fs::write(path, text::to_ron(animation)?)?),
+        "json" => Ok(std::fs::write(path, text::to_json(animation)?)?),
+        _ => anyhow::bail!("unsupported format: {}\n{}", path, USAGE),
+    }
+}
+
+fn write_import(frames: &[image::RgbaImage], output: &str, atlas: &str) -> anyhow::Result<()> {
+    if frames.is_empty() {
+        anyhow::bail!("no frames to import");
+    }
+    let texture = Path::new(atlas)
+        .file_stem()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow::anyhow!("invalid path: {}", atlas))?;
+    let options = import::ImportOptions {
+        texture: texture.to_owned(),
+        ..import::ImportOptions::default()
+    };
+    let (animation, image) = import::from_frames(frames, &options);
+    image.save(atlas)?;
+
+    save_animation(output, &animation)
+}
+
+fn load_parts(dir: impl AsRef<Path>) -> anyhow::Result<Vec<atlas::Part>> {
+    let dir = dir.as_ref();
+    let manifest = std::fs::read_to_string(dir.join(MANIFEST_FILE_NAME))?;
+    let manifest: atlas::Manifest = serde_json::from_str(&manifest)?;
+    manifest
+        .parts
+        .iter()
+        .map(|part| {
+            Ok(atlas::Part {
+                id: part.id,
+                image: image::open(dir.join(&part.file))?.to_rgba8(),
+            })
+        })
+        .collect()
+}
+
+fn extension(path: &str) -> &str {
+    Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+}
+
+fn file_name(path: &str) -> anyhow::Result<&str> {
+    Path::new(path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow::anyhow!("invalid path: {}", path))
+}
@@ -1,4 +1,3 @@
-use std::fs::File;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
@@ -7,15 +6,16 @@ use notan::draw::{CreateDraw, Draw};
 use notan::egui;
 use notan::prelude::*;
 use ringbuf::traits::{Consumer, Producer, Split};
+use rustfu_renderer::highlight::Highlight;
+use rustfu_renderer::lazy::LazyAnimation;
 use rustfu_renderer::notan::NotanBackend;
 use rustfu_renderer::player::AnimationPlayer;
 use rustfu_renderer::render::{Measure, SpriteTransform};
+use rustfu_renderer::source::{AnimationSource, ResolvedImport};
+use rustfu_renderer::timeline;
 use rustfu_renderer::types::Animation;
-use wakfudecrypt::types::interactive_element_model::InteractiveElementModel;
-use wakfudecrypt::types::monster::Monster;
-use wakfudecrypt::types::pet::Pet;
 
-use crate::resources::{AnimatedEntityKind, AnimationEntry, Resources};
+use crate::resources::{Resources, decode_options};
 use crate::ui::{UiEvent, UiState};
 use crate::writer;
 
@@ -25,26 +25,37 @@ const FRAME_TIME: u64 = 30;
 #[derive(notan::AppState)]
 pub struct AppState {
     ui: UiState,
+    animation: Option<Arc<LazyAnimation>>,
     player: Option<AnimationPlayer<NotanBackend>>,
     last_render: Instant,
+    last_advance: Instant,
 
     io_requests: ringbuf::HeapProd<SpriteRequest>,
     io_receiver: Option<oneshot::Receiver<anyhow::Result<SpriteResponse>>>,
 }
 
 impl AppState {
-    pub fn new(mut resources: Resources<File>) -> anyhow::Result<Self> {
-        let npcs = AnimationEntry::load_all::<_, Monster>(&mut resources)?;
-        let interactives = AnimationEntry::load_all::<_, InteractiveElementModel>(&mut resources)?;
-        let pets = AnimationEntry::load_all::<_, Pet>(&mut resources)?;
+    pub fn new(mut resources: Resources) -> anyhow::Result<Self> {
+        let categories = resources.load_entries()?;
+        let mut ui = UiState::new(categories);
+        let errors = resources
+            .take_errors()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        if !errors.is_empty() {
+            ui.set_error(format!("skipped archives: {}", errors.join("; ")));
+        }
         let (producer, consumer) = ringbuf::HeapRb::<SpriteRequest>::new(10).split();
 
         std::thread::spawn(move || Self::io_handler(consumer, &mut resources));
 
         Ok(Self {
-            ui: UiState::new(npcs, interactives, pets),
+            ui,
+            animation: None,
             player: None,
             last_render: Instant::now(),
+            last_advance: Instant::now(),
             io_requests: producer,
             io_receiver: None,
         })
@@ -63,7 +74,37 @@ impl AppState {
             let transform = SpriteTransform::scale(scale, scale)
                 .combine(&SpriteTransform::translate(position.x, position.y));
 
-            player.render(transform);
+            // the part picked in the inspector, or the whole entity while the mouse is over it
+            let bounds = egui::Rect::from_min_size(
+                position + egui::Vec2::from(sprite_box.min.to_array()),
+                egui::Vec2::from(sprite_box.size().to_array()),
+            );
+            let part = self.ui.highlighted_part();
+            let hovered = self.ui.pointer().is_some_and(|pos| bounds.contains(pos));
+            player.set_highlight((part.is_some() || hovered).then(|| Highlight {
+                part,
+                ..Highlight::default()
+            }));
+
+            if self.ui.smooth() && !player.is_paused() {
+                let frame_time = Duration::from_millis(FRAME_TIME);
+                let steps =
+                    (self.last_advance.elapsed().as_millis() / frame_time.as_millis()) as u32;
+                if steps > 0 {
+                    player.advance(steps);
+                    self.last_advance += frame_time * steps;
+                }
+                let progress = self.last_advance.elapsed().as_secs_f32() / frame_time.as_secs_f32();
+                player.render_tweened(transform, progress);
+            } else {
+                player.render(transform);
+                player.tick();
+                self.last_advance = Instant::now();
+            }
+            self.ui.set_frame(
+                player.frame_index(),
+                player.current_sprite().frame_count() as u32,
+            );
             let result = player.backend_mut().swap(gfx.create_draw());
 
             self.last_render = Instant::now();
@@ -76,24 +117,42 @@ impl AppState {
     fn handle_events(&mut self, gfx: &mut Graphics) {
         let resp_opt = self.io_receiver.as_mut().and_then(|r| r.try_recv().ok());
         if let Some(resp) = resp_opt {
-                let Some(SpriteResponse { animation, texture }) = self.unwrap_result(resp) else {
+                let Some(SpriteResponse {
+                    animation,
+                    texture,
+                    imports,
+                }) = self.unwrap_result(resp)
+                else {
                     return;
                 };
-                let tex = gfx
-                    .create_texture()
-                    .from_bytes(texture.as_raw(), texture.width(), texture.height())
-                    .with_filter(TextureFilter::Linear, TextureFilter::Linear)
-                    .build()
-                    .map_err(|err| anyhow::anyhow!("could not create texture: {}", err));
+                let tex = create_texture(gfx, &texture);
                 let Some(tex) = self.unwrap_result(tex) else {
                     return;
                 };
 
-                let animation = Arc::new(animation);
+                let lazy = Arc::new(animation);
+                let first = lazy
+                    .sprite_ids()
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("animation has no sprites"));
+                let animation = first.and_then(|id| lazy.animation_for(id).map_err(Into::into));
+                let Some(animation) = self.unwrap_result(animation) else {
+                    return;
+                };
                 let backend = NotanBackend::new(gfx.create_draw(), tex);
-                let player = AnimationPlayer::new(backend, animation.clone());
+                let mut player = AnimationPlayer::new(backend, animation);
 
-                self.ui.set_animation(animation);
+                self.ui.set_animation(lazy.clone());
+                self.animation = Some(lazy);
+                self.report_warnings();
+                if let Err(err) = imports.and_then(|imports| imports.apply(gfx, &mut player)) {
+                    self.ui
+                        .set_error(format!("could not resolve imports: {}", err));
+                }
+                self.ui.set_parts(timeline::parts(
+                    &player.animation(),
+                    player.current_sprite(),
+                ));
                 self.player = Some(player);
                 self.io_receiver = None;
         }
@@ -106,31 +165,94 @@ impl AppState {
 
         for event in events {
             match event {
-                UiEvent::RequestSprite(id) => {
+                UiEvent::RequestSprite(name) => {
                     let (sender, receiver) = oneshot::channel();
-                    let kind = self.ui.selected_entity();
+                    let category = self.ui.selected_category();
                     self.io_requests
-                        .try_push(SpriteRequest { id, kind, sender })
+                        .try_push(SpriteRequest {
+                            name,
+                            category,
+                            sender,
+                        })
                         .unwrap();
                     self.io_receiver = Some(receiver);
                 }
                 UiEvent::SetSprite(id) => {
+                    let Some(animation) = self.load_sprites(&[id]) else {
+                        continue;
+                    };
                     if let Some(player) = &mut self.player {
+                        player.set_animation(animation);
                         player.set_sprite(id);
+                        self.ui.set_parts(timeline::parts(
+                            &player.animation(),
+                            player.current_sprite(),
+                        ));
+                    }
+                }
+                UiEvent::SetPaused(paused) => {
+                    if let Some(player) = &mut self.player {
+                        if paused {
+                            player.pause();
+                        } else {
+                            player.resume();
+                        }
+                    }
+                }
+                UiEvent::StepBack => {
+                    if let Some(player) = &mut self.player {
+                        player.step_back();
+                    }
+                }
+                UiEvent::StepForward => {
+                    if let Some(player) = &mut self.player {
+                        player.step_forward();
+                    }
+                }
+                UiEvent::SetReverse(reverse) => {
+                    if let Some(player) = &mut self.player {
+                        player.set_reverse(reverse);
                     }
                 }
-                UiEvent::SaveAsWebp => {
+                UiEvent::SetLoopMode(mode) => {
+                    if let Some(player) = &mut self.player {
+                        player.set_loop_mode(mode);
+                    }
+                }
+                UiEvent::SetRange(range) => {
+                    if let Some(player) = &mut self.player {
+                        player.set_range(range);
+                    }
+                }
+                UiEvent::SaveAsWebp | UiEvent::SaveAsGif | UiEvent::SaveAsApng => {
                     if let Some(mut tmp) = self.create_temp_player(gfx) {
                         let result = (|| {
+                            let file_name = match event {
+                                UiEvent::SaveAsGif => "output.gif",
+                                UiEvent::SaveAsApng => "output.png",
+                                _ => "output.webp",
+                            };
                             let Some(path) = FileDialog::new()
-                                .set_file_name("output.webp")
+                                .set_file_name(file_name)
                                 .save_file()
                             else {
                                 return Ok(());
                             };
 
-                            let result = writer::write_webp(gfx, &mut tmp, DEFAULT_SCALE)?;
-                            std::fs::write(path, result)?;
+                            match event {
+                                UiEvent::SaveAsGif => {
+                                    let result = writer::write_gif(gfx, &mut tmp, DEFAULT_SCALE)?;
+                                    std::fs::write(path, result)?;
+                                }
+                                UiEvent::SaveAsApng => {
+                                    let result = writer::write_apng(gfx, &mut tmp, DEFAULT_SCALE)?;
+                                    std::fs::write(path, result)?;
+                                }
+                                _ => {
+                                    let result = writer::write_webp(gfx, &mut tmp, DEFAULT_SCALE)?;
+                                    std::fs::write(path, result)?;
+                                }
+                            }
                             Ok(())
                         })();
                         self.unwrap_result(result);
@@ -147,6 +269,57 @@ impl AppState {
                         self.unwrap_result(result);
                     }
                 }
+                UiEvent::SaveAsGodot => {
+                    let sprites = self.named_sprites();
+                    let Some(animation) = self.load_sprites(&sprites) else {
+                        continue;
+                    };
+                    if let Some(mut tmp) = self.create_temp_player(gfx) {
+                        tmp.set_animation(animation);
+                        let result = (|| {
+                            let Some(dir) = FileDialog::new().pick_folder() else {
+                                return Ok(());
+                            };
+                            writer::write_godot_sprite_frames(
+                                gfx,
+                                &mut tmp,
+                                DEFAULT_SCALE,
+                                &sprites,
+                                &dir,
+                            )
+                        })();
+                        self.unwrap_result(result);
+                    }
+                }
+                UiEvent::SaveAsSheet | UiEvent::SaveAllAsSheet => {
+                    let sprites = match (&event, &self.player) {
+                        (UiEvent::SaveAllAsSheet, _) => self.named_sprites(),
+                        (_, Some(player)) => vec![player.current_sprite_id()],
+                        (_, None) => vec![],
+                    };
+                    let Some(animation) = self.load_sprites(&sprites) else {
+                        continue;
+                    };
+                    if let Some(mut tmp) = self.create_temp_player(gfx) {
+                        tmp.set_animation(animation);
+                        let result = (|| {
+                            let Some(path) = FileDialog::new()
+                                .set_file_name("sheet.png")
+                                .save_file()
+                            else {
+                                return Ok(());
+                            };
+                            writer::write_sprite_sheet(
+                                gfx,
+                                &mut tmp,
+                                DEFAULT_SCALE,
+                                &sprites,
+                                &path,
+                            )
+                        })();
+                        self.unwrap_result(result);
+                    }
+                }
             }
         }
     }
@@ -154,26 +327,18 @@ impl AppState {
     #[allow(tail_expr_drop_order)]
     fn io_handler(
         mut consumer: ringbuf::HeapCons<SpriteRequest>,
-        resources: &mut Resources<File>,
+        resources: &mut Resources,
     ) {
         loop {
             while let Some(req) = consumer.try_pop() {
-                let source = match req.kind {
-                    AnimatedEntityKind::Monster => &mut resources.npc_animations,
-                    AnimatedEntityKind::InteractiveElementModel => {
-                        &mut resources.interactive_animations
-                    }
-                    AnimatedEntityKind::Pet => &mut resources.pet_animations,
-                };
+                let source = resources.categories[req.category].source_mut();
 
                 let res = (|| {
-                    let animation = source.load_animation(&req.id.to_string())?;
-                    let texture = animation
-                        .texture
-                        .as_ref()
-                        .ok_or_else(|| anyhow::anyhow!("animation {} has no texture", req.id))?;
-                    let texture = source.load_texture(&texture.name.clone())?;
-                    Ok(SpriteResponse::new(animation, texture))
+                    let animation = source.load_lazy_animation(&req.name, decode_options())?;
+                    let texture = source.load_animation_atlas(animation.head())?;
+                    // imported sprites are missing when this fails, but the rest can be shown
+                    let imports = Imports::load(source, animation.head());
+                    Ok(SpriteResponse::new(animation, texture, imports))
                 })();
 
                 drop(req.sender.send(res));
@@ -184,7 +349,7 @@ impl AppState {
 
     #[inline]
     pub fn should_render(&self) -> bool {
-        self.last_render.elapsed() >= Duration::from_millis(FRAME_TIME)
+        self.ui.smooth() || self.last_render.elapsed() >= Duration::from_millis(FRAME_TIME)
     }
 
     #[inline]
@@ -202,11 +367,53 @@ impl AppState {
         self.player.as_ref().map(|player| {
             let backend = player.backend().clone_with_draw(gfx.create_draw());
             let mut tmp = AnimationPlayer::new(backend, player.animation());
+            *tmp.overrides_mut() = player.overrides().clone();
             tmp.set_sprite(player.current_sprite_id());
             tmp
         })
     }
 
+    /// Sprites with a name, sorted by it.
+    fn named_sprites(&self) -> Vec<i16> {
+        let Some(lazy) = &self.animation else {
+            return vec![];
+        };
+        let mut sprites = lazy
+            .sprite_ids()
+            .filter_map(|id| Some((lazy.sprite_name(id)?.name.as_deref()?, id)))
+            .collect::<Vec<_>>();
+        sprites.sort_unstable();
+        sprites.into_iter().map(|(_, id)| id).collect()
+    }
+
+    /// Decodes sprites that were not needed yet, returning an animation holding them along with
+    /// all the sprites loaded before.
+    fn load_sprites(&mut self, sprites: &[i16]) -> Option<Arc<Animation>> {
+        let lazy = self.animation.clone()?;
+        let mut animation = self.player.as_ref().map(|player| player.animation());
+        for &id in sprites {
+            animation = Some(self.unwrap_result(lazy.animation_for(id).map_err(Into::into))?);
+        }
+        self.report_warnings();
+        animation
+    }
+
+    /// Shows the warnings collected while decoding sprites since the last call.
+    fn report_warnings(&mut self) {
+        let Some(lazy) = &self.animation else {
+            return;
+        };
+        let warnings = lazy
+            .take_warnings()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        if !warnings.is_empty() {
+            self.ui
+                .set_error(format!("decoded with warnings: {}", warnings.join("; ")));
+        }
+    }
+
     fn unwrap_result<A>(&mut self, result: anyhow::Result<A>) -> Option<A> {
         match result {
             Ok(value) => Some(value),
@@ -218,22 +425,85 @@ impl AppState {
     }
 }
 
+fn create_texture(gfx: &mut Graphics, image: &image::RgbaImage) -> anyhow::Result<Texture> {
+    gfx.create_texture()
+        .from_bytes(image.as_raw(), image.width(), image.height())
+        .with_filter(TextureFilter::Linear, TextureFilter::Linear)
+        .build()
+        .map_err(|err| anyhow::anyhow!("could not create texture: {}", err))
+}
+
 #[derive(Debug)]
 pub struct SpriteRequest {
-    id: i32,
-    kind: AnimatedEntityKind,
+    name: String,
+    category: usize,
     sender: oneshot::Sender<anyhow::Result<SpriteResponse>>,
 }
 
 #[derive(Debug)]
 pub struct SpriteResponse {
-    animation: Animation,
+    animation: LazyAnimation,
     texture: image::RgbaImage,
+    imports: anyhow::Result<Imports>,
 }
 
 impl SpriteResponse {
     #[inline]
-    pub fn new(animation: Animation, texture: image::RgbaImage) -> Self {
-        Self { animation, texture }
+    pub fn new(
+        animation: LazyAnimation,
+        texture: image::RgbaImage,
+        imports: anyhow::Result<Imports>,
+    ) -> Self {
+        Self {
+            animation,
+            texture,
+            imports,
+        }
+    }
+}
+
+/// The animations imported by a requested one, along with their atlases.
+#[derive(Debug)]
+pub struct Imports {
+    resolved: Vec<ResolvedImport>,
+    atlases: Vec<(Arc<Animation>, image::RgbaImage)>,
+}
+
+impl Imports {
+    fn load(source: &mut dyn AnimationSource, animation: &Animation) -> anyhow::Result<Self> {
+        let resolved = source.resolve_imports(animation, decode_options())?;
+        let mut atlases: Vec<(Arc<Animation>, image::RgbaImage)> = vec![];
+        for import in &resolved {
+            let loaded = atlases
+                .iter()
+                .any(|(animation, _)| Arc::ptr_eq(animation, &import.animation));
+            if !loaded {
+                let atlas = source.load_animation_atlas(&import.animation)?;
+                atlases.push((import.animation.clone(), atlas));
+            }
+        }
+        Ok(Self { resolved, atlases })
+    }
+
+    /// Adds the atlases to the backend of the player and substitutes the imports with the
+    /// sprites they refer to.
+    fn apply(
+        self,
+        gfx: &mut Graphics,
+        player: &mut AnimationPlayer<NotanBackend>,
+    ) -> anyhow::Result<()> {
+        let mut indices = Vec::with_capacity(self.atlases.len());
+        for (animation, atlas) in &self.atlases {
+            let texture = create_texture(gfx, atlas)?;
+            indices.push((animation, player.backend_mut().add_atlas(texture)));
+        }
+        for import in &self.resolved {
+            let atlas = indices
+                .iter()
+                .find(|(animation, _)| Arc::ptr_eq(animation, &import.animation))
+                .map_or(0, |&(_, atlas)| atlas);
+            import.apply(player.overrides_mut(), atlas);
+        }
+        Ok(())
     }
 }
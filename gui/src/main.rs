@@ -9,19 +9,32 @@ use resources::Resources;
 
 mod app;
 mod resources;
+mod sheet;
 mod translations;
 mod ui;
 mod writer;
 
 #[notan_main]
 fn main() {
-    MessageDialog::new()
+    let choice = MessageDialog::new()
         .set_title("Rustfu")
-        .set_description("Select the Wakfu installation folder")
-        .set_buttons(rfd::MessageButtons::Ok)
+        .set_description(
+            "Select the Wakfu installation folder or a folder of animations, \
+             or open a single .anm or .jar file",
+        )
+        .set_buttons(rfd::MessageButtons::OkCancelCustom(
+            "Folder".to_owned(),
+            "File".to_owned(),
+        ))
         .show();
 
-    let Some(path) = FileDialog::new().pick_folder() else {
+    let path = match choice {
+        rfd::MessageDialogResult::Custom(label) if label == "File" => FileDialog::new()
+            .add_filter("Animations", &["anm", "jar"])
+            .pick_file(),
+        _ => FileDialog::new().pick_folder(),
+    };
+    let Some(path) = path else {
         return;
     };
 
@@ -1,83 +1,272 @@
-use std::fs::File;
+use std::ffi::OsStr;
 use std::io;
-use std::io::{Cursor, Read};
 use std::path::{Path, PathBuf};
 
-use rustfu_renderer::types::Animation;
+use rustfu_renderer::decode::{DecodeOptions, Decoder, StringEncoding};
+use rustfu_renderer::source::{AnimationSource, DirectorySource, JarSource, MemorySource};
 use wakfudecrypt::document::Document;
 use wakfudecrypt::types::interactive_element_model::InteractiveElementModel;
 use wakfudecrypt::types::monster::Monster;
 use wakfudecrypt::types::pet::Pet;
 use wakfudecrypt::BinaryData;
-use zip::ZipArchive;
 
 use crate::translations::Translations;
 
-#[derive(Debug)]
-pub struct Resources<R: io::Read + io::Seek> {
-    root: PathBuf,
+pub struct Resources {
+    game: Option<GameData>,
+    pub categories: Vec<AnimationCategory>,
+    errors: Vec<io::Error>,
+}
 
-    pub npc_animations: AnimationArchive<R>,
-    pub interactive_animations: AnimationArchive<R>,
-    pub pet_animations: AnimationArchive<R>,
+impl Resources {
+    /// Opens a Wakfu installation, a directory of extracted animations, a `.jar` archive or a
+    /// single `.anm` file.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Resources> {
+        let path = path.as_ref();
+        if path.join("contents").join("animations").is_dir() {
+            return Resources::open_install(path);
+        }
 
-    pub translations: Translations,
-}
+        let label = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let source: Box<dyn AnimationSource + Send> = if path.is_dir() {
+            Box::new(DirectorySource::new(path))
+        } else {
+            match path.extension().and_then(OsStr::to_str) {
+                Some("jar" | "zip") => Box::new(JarSource::open(path)?),
+                Some("anm") => Box::new(open_loose_animation(path)?),
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "{} is not an animation, archive or directory",
+                            path.display()
+                        ),
+                    ));
+                }
+            }
+        };
 
-impl Resources<File> {
-    pub fn open(root: impl AsRef<Path>) -> io::Result<Resources<File>> {
-        let anim_root = root.as_ref().join("contents").join("animations");
-        let npcs = AnimationArchive::open(anim_root.join("npcs").join("npcs.jar"))?;
-        let interactives =
-            AnimationArchive::open(anim_root.join("interactives").join("interactives.jar"))?;
-        let pets = AnimationArchive::open(anim_root.join("pets").join("pets.jar"))?;
+        Ok(Resources {
+            game: None,
+            categories: vec![AnimationCategory::new(label, None, source)],
+            errors: vec![],
+        })
+    }
+
+    /// Opens every archive of an installation, skipping the ones that cannot be opened, which
+    /// are kept as [errors](Resources::take_errors).
+    fn open_install(root: &Path) -> io::Result<Resources> {
+        let anim_root = root.join("contents").join("animations");
+        let mut archives = vec![];
+        find_archives(&anim_root, &mut archives)?;
+        let mut categories = vec![];
+        let mut errors = vec![];
+        for path in archives {
+            let source = match JarSource::open(&path) {
+                Ok(source) => source,
+                Err(err) => {
+                    errors.push(io::Error::new(
+                        err.kind(),
+                        format!("{}: {}", path.display(), err),
+                    ));
+                    continue;
+                }
+            };
+            // archives are told apart by their path, as several share the same name
+            let relative = path.strip_prefix(&anim_root).unwrap_or(&path);
+            let category = match AnimatedEntityKind::from_archive(relative) {
+                Some(kind) => AnimationCategory::of_kind(kind, Box::new(source)),
+                None => AnimationCategory::new(archive_label(relative), None, Box::new(source)),
+            };
+            categories.push(category);
+        }
+        // categories with game data come first, in the order of their kinds
+        categories.sort_by(|a, b| {
+            (a.kind.is_none(), a.kind, &a.label).cmp(&(b.kind.is_none(), b.kind, &b.label))
+        });
 
-        let translations = Translations::load(
-            root.as_ref()
-                .join("contents")
-                .join("i18n")
-                .join("i18n_en.jar"),
-        )?;
+        let translations =
+            Translations::load(root.join("contents").join("i18n").join("i18n_en.jar"))?;
 
         Ok(Resources {
-            root: root.as_ref().to_owned(),
-            npc_animations: npcs,
-            interactive_animations: interactives,
-            pet_animations: pets,
-            translations,
+            game: Some(GameData {
+                root: root.to_owned(),
+                translations,
+            }),
+            categories,
+            errors,
         })
     }
+
+    /// Takes the errors of the archives that were skipped while opening an installation.
+    #[inline]
+    pub fn take_errors(&mut self) -> Vec<io::Error> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// Lists the label and the entries of every category.
+    pub fn load_entries(&mut self) -> io::Result<Vec<(String, Vec<AnimationEntry>)>> {
+        self.categories
+            .iter_mut()
+            .map(|category| {
+                let entries = category.load_entries(self.game.as_ref())?;
+                Ok((category.label.clone(), entries))
+            })
+            .collect()
+    }
 }
 
-impl<R: io::Read + io::Seek> Resources<R> {
-    pub fn load_data<A: BinaryData>(&mut self) -> io::Result<Document<A>> {
-        Document::load(&self.root)
+/// Collects the `.jar` archives in a directory and its subdirectories, sorted by path.
+fn find_archives(dir: &Path, archives: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries = std::fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.path());
+    for entry in entries {
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            find_archives(&path, archives)?;
+        } else if path.extension() == Some(OsStr::new("jar")) {
+            archives.push(path);
+        }
     }
+    Ok(())
+}
+
+/// Labels an archive by its path relative to the animations, e.g. `Players/players` for
+/// `players/players.jar`.
+fn archive_label(relative: &Path) -> String {
+    let label = relative
+        .with_extension("")
+        .iter()
+        .map(OsStr::to_string_lossy)
+        .collect::<Vec<_>>()
+        .join("/");
+    capitalize(&label)
 }
 
+#[inline]
+fn capitalize(name: &str) -> String {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+/// Wraps a loose `.anm` file along with its atlas, which is looked up next to it.
+fn open_loose_animation(path: &Path) -> io::Result<MemorySource> {
+    let data = std::fs::read(path)?;
+    let name = path
+        .file_stem()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    // only the texture name is needed, the sprites are decoded when the animation is played
+    let head = Decoder::new(decode_options()).decode_head(&mut io::Cursor::new(&data))?;
+
+    let mut source = MemorySource::new();
+    source.insert_animation(&name, data);
+    if let Some(texture) = &head.texture {
+        let dir = path.parent().unwrap_or(Path::new(""));
+        // the atlas may be laid out like in the archives, or sit right next to the animation
+        let candidates = [
+            dir.join("Atlas").join(format!("{}.png", texture.name)),
+            dir.join(format!("{}.png", texture.name)),
+            dir.join(format!("{}.png", name)),
+        ];
+        let Some(atlas) = candidates.iter().find_map(|path| std::fs::read(path).ok()) else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "no atlas found for {}, looked for {}",
+                    path.display(),
+                    candidates.map(|path| path.display().to_string()).join(", ")
+                ),
+            ));
+        };
+        source.insert_atlas(&texture.name, atlas);
+    }
+    Ok(source)
+}
+
+/// Game data available when the resources come from a Wakfu installation.
 #[derive(Debug)]
-pub struct AnimationArchive<R> {
-    archive: ZipArchive<R>,
+pub struct GameData {
+    root: PathBuf,
+    translations: Translations,
+}
+
+impl GameData {
+    pub fn load_data<A: BinaryData>(&self) -> io::Result<Document<A>> {
+        Document::load(&self.root)
+    }
+}
+
+/// A browsable set of animations.
+pub struct AnimationCategory {
+    label: String,
+    kind: Option<AnimatedEntityKind>,
+    source: Box<dyn AnimationSource + Send>,
 }
 
-impl AnimationArchive<File> {
-    pub fn open(path: impl AsRef<Path>) -> io::Result<AnimationArchive<File>> {
-        let file = File::open(&path)?;
-        let archive = ZipArchive::new(file)?;
-        Ok(AnimationArchive { archive })
+impl AnimationCategory {
+    #[inline]
+    pub fn new(
+        label: String,
+        kind: Option<AnimatedEntityKind>,
+        source: Box<dyn AnimationSource + Send>,
+    ) -> Self {
+        Self {
+            label,
+            kind,
+            source,
+        }
+    }
+
+    #[inline]
+    pub fn of_kind(kind: AnimatedEntityKind, source: Box<dyn AnimationSource + Send>) -> Self {
+        Self::new(kind.label().to_owned(), Some(kind), source)
+    }
+
+    #[inline]
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    #[inline]
+    pub fn source_mut(&mut self) -> &mut dyn AnimationSource {
+        self.source.as_mut()
     }
 
-    pub fn load_animation(&mut self, id: &str) -> io::Result<Animation> {
-        let mut entry = self.archive.by_name(&format!("{}.anm", id))?;
-        rustfu_renderer::decode::Decode::decode(&mut entry)
+    /// Lists the animations of the category, labelled from the game data when there is any
+    /// for its kind, and by file name otherwise.
+    pub fn load_entries(&mut self, game: Option<&GameData>) -> io::Result<Vec<AnimationEntry>> {
+        match (self.kind, game) {
+            (Some(AnimatedEntityKind::Monster), Some(game)) => {
+                AnimationEntry::load_all::<Monster>(game)
+            }
+            (Some(AnimatedEntityKind::InteractiveElementModel), Some(game)) => {
+                AnimationEntry::load_all::<InteractiveElementModel>(game)
+            }
+            (Some(AnimatedEntityKind::Pet), Some(game)) => AnimationEntry::load_all::<Pet>(game),
+            _ => {
+                let mut names = self.source.animation_names()?;
+                // most animations are named by their numeric ids
+                names.sort_by_cached_key(|name| (name.parse::<u64>().ok(), name.clone()));
+                Ok(names.into_iter().map(AnimationEntry::unlabelled).collect())
+            }
+        }
     }
+}
 
-    pub fn load_texture(&mut self, id: &str) -> anyhow::Result<image::RgbaImage> {
-        let mut entry = self.archive.by_name(&format!("Atlas/{}.png", id))?;
-        let mut buf = Vec::with_capacity(entry.size() as usize);
-        entry.read_to_end(&mut buf)?;
-        let image = image::load(Cursor::new(buf), image::ImageFormat::Png)?.to_rgba8();
-        Ok(image)
+/// Options animations from the game are decoded with.
+pub fn decode_options() -> DecodeOptions {
+    // files from newer clients may use actions or sprites we do not know about yet, and
+    // some older ones have names in Latin-1
+    DecodeOptions {
+        lenient: true,
+        strings: StringEncoding::Latin1,
     }
 }
 
@@ -131,7 +320,7 @@ impl AnimatedEntity for Pet {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum AnimatedEntityKind {
     Monster,
     InteractiveElementModel,
@@ -139,6 +328,28 @@ pub enum AnimatedEntityKind {
 }
 
 impl AnimatedEntityKind {
+    /// The kind of entity the animations of an archive belong to, by the path of the archive
+    /// relative to the animations of an installation.
+    pub fn from_archive(relative: &Path) -> Option<Self> {
+        [
+            AnimatedEntityKind::Monster,
+            AnimatedEntityKind::InteractiveElementModel,
+            AnimatedEntityKind::Pet,
+        ]
+        .into_iter()
+        .find(|kind| relative == kind.archive())
+    }
+
+    /// Path of the archive of the kind, relative to the animations of an installation.
+    fn archive(self) -> PathBuf {
+        let name = match self {
+            AnimatedEntityKind::Monster => "npcs",
+            AnimatedEntityKind::InteractiveElementModel => "interactives",
+            AnimatedEntityKind::Pet => "pets",
+        };
+        Path::new(name).join(format!("{}.jar", name))
+    }
+
     pub fn label(self) -> &'static str {
         match self {
             AnimatedEntityKind::Monster => "Monsters",
@@ -151,41 +362,50 @@ impl AnimatedEntityKind {
 #[derive(Debug)]
 pub struct AnimationEntry {
     label: String,
-    id: i32,
+    name: String,
 }
 
 impl AnimationEntry {
-    pub fn load_all<R, A>(resources: &mut Resources<R>) -> io::Result<Vec<Self>>
+    pub fn load_all<A>(game: &GameData) -> io::Result<Vec<Self>>
     where
-        R: io::Read + io::Seek,
         A: BinaryData + AnimatedEntity,
     {
-        let result = resources
+        let result = game
             .load_data::<A>()?
             .elements
             .iter()
             .map(|elem| {
-                let label = resources
+                let label = game
                     .translations
                     .get(A::TRANSLATION_ID, &elem.id().to_string());
                 AnimationEntry {
                     label: label
                         .cloned()
                         .unwrap_or_else(|| format!("Unnamed {}", elem.id())),
-                    id: elem.gfx(),
+                    name: elem.gfx().to_string(),
                 }
             })
             .collect();
         Ok(result)
     }
 
+    /// An entry labelled by the name of its animation.
+    #[inline]
+    pub fn unlabelled(name: String) -> Self {
+        Self {
+            label: name.clone(),
+            name,
+        }
+    }
+
     #[inline]
     pub fn label(&self) -> &str {
         &self.label
     }
 
+    /// Name of the animation within its source.
     #[inline]
-    pub fn id(&self) -> i32 {
-        self.id
+    pub fn name(&self) -> &str {
+        &self.name
     }
 }
@@ -0,0 +1,287 @@
+use std::path::Path;
+
+use image::RgbaImage;
+use rustfu_renderer::atlas::{self, Region};
+use serde::Serialize;
+
+/// Width sheets are wrapped at, the largest texture size supported by most GPUs.
+pub const MAX_WIDTH: u32 = 4096;
+
+/// Frames of a single sprite rendered for a sprite sheet.
+#[derive(Debug)]
+pub struct SheetAnimation {
+    pub name: String,
+    pub frames: Vec<RgbaImage>,
+    /// Position of the sprite origin within each frame, normalized to the frame size.
+    pub pivot: (f32, f32),
+    pub frame_duration: u32,
+}
+
+/// Packs the frames of the given animations into a single image, one row per animation,
+/// wrapped onto more rows when it gets wider than `max_width`. Transparent borders are
+/// trimmed and recorded in the descriptor.
+pub fn pack(
+    animations: &[SheetAnimation],
+    image_name: &str,
+    max_width: u32,
+) -> (RgbaImage, SheetDescriptor) {
+    let trimmed = animations
+        .iter()
+        .map(|anim| anim.frames.iter().map(atlas::trim).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
+    // frames wider than the sheet get a row of their own
+    let mut positions = Vec::with_capacity(trimmed.len());
+    let (mut width, mut y) = (0, 0);
+    for row in &trimmed {
+        let (mut x, mut row_height) = (0, 0);
+        let mut row_positions = Vec::with_capacity(row.len());
+        for region in row {
+            if x > 0 && x + region.width > max_width {
+                y += row_height;
+                (x, row_height) = (0, 0);
+            }
+            row_positions.push((x, y));
+            x += region.width;
+            width = width.max(x);
+            row_height = row_height.max(region.height);
+        }
+        y += row_height;
+        positions.push(row_positions);
+    }
+    let height = y;
+
+    let mut sheet = RgbaImage::new(width.max(1), height.max(1));
+    let mut frames = vec![];
+    let mut tags = vec![];
+
+    for ((anim, row), row_positions) in animations.iter().zip(&trimmed).zip(&positions) {
+        let from = frames.len();
+        for (i, ((source, region), &(x, y))) in
+            anim.frames.iter().zip(row).zip(row_positions).enumerate()
+        {
+            let view =
+                image::imageops::crop_imm(source, region.x, region.y, region.width, region.height);
+            image::imageops::replace(&mut sheet, &*view, x.into(), y.into());
+
+            frames.push(SheetFrame {
+                filename: format!("{}_{}", anim.name, i),
+                frame: Rect {
+                    x,
+                    y,
+                    w: region.width,
+                    h: region.height,
+                },
+                rotated: false,
+                trimmed: region.width != source.width() || region.height != source.height(),
+                sprite_source_size: Rect::from(*region),
+                source_size: Size {
+                    w: source.width(),
+                    h: source.height(),
+                },
+                pivot: Point {
+                    x: anim.pivot.0,
+                    y: anim.pivot.1,
+                },
+                duration: anim.frame_duration,
+            });
+        }
+        tags.push(FrameTag {
+            name: anim.name.clone(),
+            from,
+            to: frames.len().saturating_sub(1),
+            direction: "forward",
+        });
+    }
+
+    let descriptor = SheetDescriptor {
+        frames,
+        meta: SheetMeta {
+            app: "rustfu",
+            version: "1.0",
+            image: image_name.to_owned(),
+            format: "RGBA8888",
+            size: Size {
+                w: sheet.width(),
+                h: sheet.height(),
+            },
+            scale: "1",
+            frame_tags: tags,
+        },
+    };
+    (sheet, descriptor)
+}
+
+/// Writes the sheet image and its JSON descriptor next to it.
+pub fn write(animations: &[SheetAnimation], path: &Path) -> anyhow::Result<()> {
+    let image_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("sheet.png");
+    let (sheet, descriptor) = pack(animations, image_name, MAX_WIDTH);
+    sheet.save(path)?;
+    let json = serde_json::to_string_pretty(&descriptor)?;
+    std::fs::write(path.with_extension("json"), json)?;
+    Ok(())
+}
+
+/// A TexturePacker-compatible sprite sheet descriptor (JSON array format).
+#[derive(Debug, Serialize)]
+pub struct SheetDescriptor {
+    pub frames: Vec<SheetFrame>,
+    pub meta: SheetMeta,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SheetFrame {
+    pub filename: String,
+    pub frame: Rect,
+    pub rotated: bool,
+    pub trimmed: bool,
+    pub sprite_source_size: Rect,
+    pub source_size: Size,
+    pub pivot: Point,
+    /// Duration of the frame in milliseconds.
+    pub duration: u32,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SheetMeta {
+    pub app: &'static str,
+    pub version: &'static str,
+    pub image: String,
+    pub format: &'static str,
+    pub size: Size,
+    pub scale: &'static str,
+    pub frame_tags: Vec<FrameTag>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FrameTag {
+    pub name: String,
+    pub from: usize,
+    pub to: usize,
+    pub direction: &'static str,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+impl From<Region> for Rect {
+    #[inline]
+    fn from(region: Region) -> Self {
+        Rect {
+            x: region.x,
+            y: region.y,
+            w: region.width,
+            h: region.height,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Size {
+    pub w: u32,
+    pub h: u32,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Point {
+    pub x: f32,
+    pub y: f32,
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    /// A 10x10 frame with an opaque 4x5 block at (2, 3), colored after the frame index.
+    fn frame(index: u8) -> RgbaImage {
+        let mut image = RgbaImage::new(10, 10);
+        for y in 3..8 {
+            for x in 2..6 {
+                image.put_pixel(x, y, Rgba([index, 0, 0, 255]));
+            }
+        }
+        image
+    }
+
+    fn animation(name: &str, frames: u8, pivot: (f32, f32)) -> SheetAnimation {
+        SheetAnimation {
+            name: name.to_owned(),
+            frames: (0..frames).map(frame).collect(),
+            pivot,
+            frame_duration: 33,
+        }
+    }
+
+    #[test]
+    fn wraps_rows_at_the_maximum_width() {
+        let animations = [
+            animation("walk", 3, (0.5, 1.)),
+            animation("idle", 1, (0.25, 0.75)),
+        ];
+        let (sheet, descriptor) = pack(&animations, "sheet.png", 10);
+
+        let rects = descriptor
+            .frames
+            .iter()
+            .map(|frame| (frame.filename.as_str(), frame.frame.x, frame.frame.y))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rects,
+            [
+                ("walk_0", 0, 0),
+                ("walk_1", 4, 0),
+                ("walk_2", 0, 5),
+                ("idle_0", 0, 10)
+            ]
+        );
+        assert_eq!((sheet.width(), sheet.height()), (8, 15));
+        assert_eq!((descriptor.meta.size.w, descriptor.meta.size.h), (8, 15));
+        assert_eq!(sheet.get_pixel(0, 5), &Rgba([2, 0, 0, 255]));
+
+        let tags = descriptor
+            .meta
+            .frame_tags
+            .iter()
+            .map(|tag| (tag.name.as_str(), tag.from, tag.to))
+            .collect::<Vec<_>>();
+        assert_eq!(tags, [("walk", 0, 2), ("idle", 3, 3)]);
+    }
+
+    #[test]
+    fn records_trimmed_frames_and_pivots() {
+        let animations = [
+            animation("walk", 2, (0.5, 1.)),
+            animation("idle", 1, (0.25, 0.75)),
+        ];
+        let (_, descriptor) = pack(&animations, "sheet.png", MAX_WIDTH);
+
+        let walk = &descriptor.frames[1];
+        assert_eq!(
+            (walk.frame.x, walk.frame.y, walk.frame.w, walk.frame.h),
+            (4, 0, 4, 5)
+        );
+        assert!(walk.trimmed);
+        let source = walk.sprite_source_size;
+        assert_eq!((source.x, source.y, source.w, source.h), (2, 3, 4, 5));
+        assert_eq!((walk.source_size.w, walk.source_size.h), (10, 10));
+        assert_eq!((walk.pivot.x, walk.pivot.y), (0.5, 1.));
+        assert_eq!(walk.duration, 33);
+
+        let idle = &descriptor.frames[2];
+        assert_eq!((idle.frame.x, idle.frame.y), (0, 5));
+        assert_eq!((idle.pivot.x, idle.pivot.y), (0.25, 0.75));
+        assert_eq!(descriptor.meta.image, "sheet.png");
+    }
+}
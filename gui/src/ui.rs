@@ -1,53 +1,60 @@
 use std::sync::Arc;
 
 use notan::egui;
-use rustfu_renderer::types::Animation;
+use rustfu_renderer::lazy::LazyAnimation;
+use rustfu_renderer::player::LoopMode;
 
-use crate::resources::{AnimatedEntityKind, AnimationEntry};
+use crate::resources::AnimationEntry;
 
 #[derive(Debug)]
 pub struct UiState {
-    npcs: Vec<AnimationEntry>,
-    interactives: Vec<AnimationEntry>,
-    pets: Vec<AnimationEntry>,
+    categories: Vec<(String, Vec<AnimationEntry>)>,
 
-    animation: Option<Arc<Animation>>,
-    selected_entity: AnimatedEntityKind,
+    animation: Option<Arc<LazyAnimation>>,
+    selected_category: usize,
     filter: String,
     filtered_entries: Option<Vec<usize>>,
     error: Option<String>,
     available_space: egui::Rect,
+    smooth: bool,
+    playback: PlaybackState,
+    parts: Vec<i16>,
+    selected_part: Option<i16>,
+    hovered_part: Option<i16>,
+    pointer: Option<egui::Pos2>,
 
     events: Vec<UiEvent>,
 }
 
 impl UiState {
-    pub fn new(
-        npcs: Vec<AnimationEntry>,
-        interactives: Vec<AnimationEntry>,
-        pets: Vec<AnimationEntry>,
-    ) -> Self {
+    pub fn new(categories: Vec<(String, Vec<AnimationEntry>)>) -> Self {
         Self {
-            npcs,
-            interactives,
-            pets,
+            categories,
             animation: None,
-            selected_entity: AnimatedEntityKind::Monster,
+            selected_category: 0,
             filter: String::new(),
             filtered_entries: None,
             error: None,
             available_space: egui::Rect::ZERO,
+            smooth: false,
+            playback: PlaybackState::default(),
+            parts: Vec::new(),
+            selected_part: None,
+            hovered_part: None,
+            pointer: None,
             events: Vec::new(),
         }
     }
 
     pub fn draw(&mut self, ctx: &egui::Context) {
+        self.pointer = ctx.input(|input| input.pointer.hover_pos());
         egui::CentralPanel::default().show(ctx, |ui| {
             self.draw_top_bar(ui);
 
             ui.horizontal_top(|ui| {
                 self.draw_anim_list(ui);
                 self.draw_sprite_list(ui);
+                self.draw_inspector(ui);
 
                 self.available_space = ui.available_rect_before_wrap();
             });
@@ -57,28 +64,53 @@ impl UiState {
     fn draw_top_bar(&mut self, ui: &mut egui::Ui) {
         egui::TopBottomPanel::top("Tabs").show_inside(ui, |ui| {
             ui.horizontal(|ui| {
-                for item in [
-                    AnimatedEntityKind::Monster,
-                    AnimatedEntityKind::InteractiveElementModel,
-                    AnimatedEntityKind::Pet,
-                ] {
-                    if ui
-                        .selectable_label(self.selected_entity == item, item.label())
-                        .clicked()
-                    {
-                        self.selected_entity = item;
-                        self.filtered_entries = None;
-                    }
-                }
+                // installs ship too many archives to fit as tabs
+                let selected = self
+                    .categories
+                    .get(self.selected_category)
+                    .map_or("", |(label, _)| label.as_str());
+                egui::ComboBox::from_id_salt("Category")
+                    .selected_text(selected)
+                    .height(400.)
+                    .show_ui(ui, |ui| {
+                        for (i, (label, _)) in self.categories.iter().enumerate() {
+                            if ui
+                                .selectable_label(self.selected_category == i, label)
+                                .clicked()
+                            {
+                                self.selected_category = i;
+                                self.filtered_entries = None;
+                            }
+                        }
+                    });
 
                 ui.separator();
 
                 if ui.button("Save as WEBP").clicked() {
                     self.events.push(UiEvent::SaveAsWebp);
                 }
+                if ui.button("Save as GIF").clicked() {
+                    self.events.push(UiEvent::SaveAsGif);
+                }
+                if ui.button("Save as APNG").clicked() {
+                    self.events.push(UiEvent::SaveAsApng);
+                }
                 if ui.button("Save as Frames").clicked() {
                     self.events.push(UiEvent::SaveAsFrames);
                 }
+                if ui.button("Save as Sheet").clicked() {
+                    self.events.push(UiEvent::SaveAsSheet);
+                }
+                if ui.button("Save All as Sheet").clicked() {
+                    self.events.push(UiEvent::SaveAllAsSheet);
+                }
+                if ui.button("Save as Godot").clicked() {
+                    self.events.push(UiEvent::SaveAsGodot);
+                }
+
+                ui.separator();
+
+                ui.checkbox(&mut self.smooth, "Smooth");
 
                 ui.separator();
 
@@ -86,10 +118,66 @@ impl UiState {
                     ui.colored_label(egui::Color32::RED, error);
                 }
             });
+            ui.horizontal(|ui| self.draw_playback_bar(ui));
             ui.add_space(4.);
         });
     }
 
+    fn draw_playback_bar(&mut self, ui: &mut egui::Ui) {
+        let state = &mut self.playback;
+        if ui
+            .button(if state.paused { "Play" } else { "Pause" })
+            .clicked()
+        {
+            state.paused = !state.paused;
+            self.events.push(UiEvent::SetPaused(state.paused));
+        }
+        if ui.button("Step Back").clicked() {
+            self.events.push(UiEvent::StepBack);
+        }
+        if ui.button("Step Forward").clicked() {
+            self.events.push(UiEvent::StepForward);
+        }
+        if ui.checkbox(&mut state.reverse, "Reverse").changed() {
+            self.events.push(UiEvent::SetReverse(state.reverse));
+        }
+        egui::ComboBox::from_id_salt("LoopMode")
+            .selected_text(loop_mode_label(state.loop_mode))
+            .show_ui(ui, |ui| {
+                for mode in [LoopMode::Loop, LoopMode::Once, LoopMode::PingPong] {
+                    if ui
+                        .selectable_value(&mut state.loop_mode, mode, loop_mode_label(mode))
+                        .changed()
+                    {
+                        self.events.push(UiEvent::SetLoopMode(mode));
+                    }
+                }
+            });
+
+        ui.separator();
+
+        let last = state.frame_count.saturating_sub(1);
+        let mut changed = ui.checkbox(&mut state.range_enabled, "Range").changed();
+        changed |= ui
+            .add(egui::DragValue::new(&mut state.range.0).range(0..=last))
+            .changed();
+        changed |= ui
+            .add(egui::DragValue::new(&mut state.range.1).range(0..=last))
+            .changed();
+        if changed {
+            let range = state.range_enabled.then_some(state.range);
+            self.events.push(UiEvent::SetRange(range));
+        }
+
+        ui.separator();
+
+        ui.label(format!(
+            "Frame {}/{}",
+            state.frame + 1,
+            state.frame_count.max(1)
+        ));
+    }
+
     fn draw_anim_list(&mut self, ui: &mut egui::Ui) {
         let text_style = egui::TextStyle::Body;
         let row_height = ui.text_style_height(&text_style);
@@ -110,11 +198,10 @@ impl UiState {
 
                 ui.separator();
 
-                let entries = match self.selected_entity {
-                    AnimatedEntityKind::Monster => &self.npcs,
-                    AnimatedEntityKind::InteractiveElementModel => &self.interactives,
-                    AnimatedEntityKind::Pet => &self.pets,
-                };
+                let entries = self
+                    .categories
+                    .get(self.selected_category)
+                    .map_or(&[][..], |(_, entries)| entries);
 
                 let filtered = self.filtered_entries.get_or_insert_with(|| {
                     entries
@@ -131,7 +218,8 @@ impl UiState {
                         for &idx in &filtered[row_range.start..row_range.end] {
                             let entry = &entries[idx];
                             if ui.selectable_label(false, entry.label()).clicked() {
-                                self.events.push(UiEvent::RequestSprite(entry.id()));
+                                self.events
+                                    .push(UiEvent::RequestSprite(entry.name().to_owned()));
                             }
                         }
                     });
@@ -147,11 +235,15 @@ impl UiState {
                     egui::ScrollArea::vertical()
                         .auto_shrink([false, false])
                         .show(ui, |ui| {
-                            for (&id, sprite) in &animation.sprites {
-                                let Some(name) = sprite.name.name.as_deref() else {
+                            for id in animation.sprite_ids() {
+                                let Some(name) = animation
+                                    .sprite_name(id)
+                                    .and_then(|name| name.name.as_deref())
+                                else {
                                     continue;
                                 };
                                 if ui.selectable_label(false, name).clicked() {
+                                    self.playback.range_enabled = false;
                                     self.events.push(UiEvent::SetSprite(id));
                                 }
                             }
@@ -160,19 +252,85 @@ impl UiState {
         }
     }
 
+    fn draw_inspector(&mut self, ui: &mut egui::Ui) {
+        let Some(animation) = &self.animation else {
+            return;
+        };
+        self.hovered_part = None;
+        egui::SidePanel::new(egui::panel::Side::Left, "Inspector")
+            .exact_width(160.)
+            .resizable(false)
+            .show_inside(ui, |ui| {
+                egui::ScrollArea::vertical()
+                    .auto_shrink([false, false])
+                    .show(ui, |ui| {
+                        for &id in &self.parts {
+                            let label = match animation.sprite_name(id) {
+                                Some(name) => match name.name.as_deref() {
+                                    Some(name) => format!("{} ({})", name, id),
+                                    None => format!("Sprite {}", id),
+                                },
+                                None => format!("Shape {}", id),
+                            };
+                            let selected = self.selected_part == Some(id);
+                            let response = ui.selectable_label(selected, label);
+                            if response.clicked() {
+                                self.selected_part = if selected { None } else { Some(id) };
+                            }
+                            if response.hovered() {
+                                self.hovered_part = Some(id);
+                            }
+                        }
+                    });
+            });
+    }
+
     #[inline]
     pub fn available_space(&self) -> egui::Rect {
         self.available_space
     }
 
+    /// Whether frames should be interpolated and rendered at the display refresh rate.
+    #[inline]
+    pub fn smooth(&self) -> bool {
+        self.smooth
+    }
+
+    /// Updates the frame shown in the playback bar.
     #[inline]
-    pub fn selected_entity(&self) -> AnimatedEntityKind {
-        self.selected_entity
+    pub fn set_frame(&mut self, frame: u32, frame_count: u32) {
+        self.playback.frame = frame;
+        self.playback.frame_count = frame_count;
     }
 
+    /// Sets the parts listed in the inspector, clearing the selection.
     #[inline]
-    pub fn set_animation(&mut self, animation: Arc<Animation>) {
+    pub fn set_parts(&mut self, parts: Vec<i16>) {
+        self.parts = parts;
+        self.selected_part = None;
+    }
+
+    /// The part hovered or selected in the inspector.
+    #[inline]
+    pub fn highlighted_part(&self) -> Option<i16> {
+        self.hovered_part.or(self.selected_part)
+    }
+
+    /// Position of the mouse pointer, if it is over the window.
+    #[inline]
+    pub fn pointer(&self) -> Option<egui::Pos2> {
+        self.pointer
+    }
+
+    #[inline]
+    pub fn selected_category(&self) -> usize {
+        self.selected_category
+    }
+
+    #[inline]
+    pub fn set_animation(&mut self, animation: Arc<LazyAnimation>) {
         self.animation = Some(animation);
+        self.playback = PlaybackState::default();
     }
 
     #[inline]
@@ -193,8 +351,38 @@ impl UiState {
 
 #[derive(Debug)]
 pub enum UiEvent {
-    RequestSprite(i32),
+    RequestSprite(String),
     SetSprite(i16),
     SaveAsWebp,
+    SaveAsGif,
+    SaveAsApng,
     SaveAsFrames,
+    SaveAsSheet,
+    SaveAllAsSheet,
+    SaveAsGodot,
+    SetPaused(bool),
+    StepBack,
+    StepForward,
+    SetReverse(bool),
+    SetLoopMode(LoopMode),
+    SetRange(Option<(u32, u32)>),
+}
+
+#[derive(Debug, Default)]
+struct PlaybackState {
+    paused: bool,
+    reverse: bool,
+    loop_mode: LoopMode,
+    range_enabled: bool,
+    range: (u32, u32),
+    frame: u32,
+    frame_count: u32,
+}
+
+fn loop_mode_label(mode: LoopMode) -> &'static str {
+    match mode {
+        LoopMode::Loop => "Loop",
+        LoopMode::Once => "Once",
+        LoopMode::PingPong => "Ping-Pong",
+    }
 }
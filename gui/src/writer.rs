@@ -1,14 +1,20 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use euclid::default::Box2D;
+use image::codecs::gif::{GifEncoder, Repeat};
 use notan::app::{Color, Graphics};
 use notan::draw::CreateDraw;
+use rustfu_renderer::godot;
 use rustfu_renderer::notan::NotanBackend;
 use rustfu_renderer::player::AnimationPlayer;
 use rustfu_renderer::render::{Measure, SpriteTransform};
 
+use crate::sheet::{self, SheetAnimation};
+
 const FRAME_PADDING: f32 = 96.;
 const FRAME_TIME: i32 = 30;
+const GIF_SPEED: i32 = 10;
+const GIF_ALPHA_THRESHOLD: u8 = 128;
 
 trait FrameWriter {
     fn write_frame(&mut self, bytes: &[u8], frame: usize) -> anyhow::Result<()>;
@@ -32,6 +38,87 @@ pub fn write_webp(
     Ok(writer.finalize(player.current_sprite().frame_count() as i32 * FRAME_TIME)?)
 }
 
+struct GifFrames<'a> {
+    encoder: GifEncoder<&'a mut Vec<u8>>,
+    width: u32,
+    height: u32,
+    delay: image::Delay,
+}
+
+impl FrameWriter for GifFrames<'_> {
+    fn write_frame(&mut self, bytes: &[u8], _frame: usize) -> anyhow::Result<()> {
+        let mut img = image::RgbaImage::from_raw(self.width, self.height, bytes.to_vec())
+            .ok_or_else(|| anyhow::anyhow!("generated image was invalid"))?;
+        // GIF only supports binary transparency
+        for pixel in img.pixels_mut() {
+            if pixel[3] < GIF_ALPHA_THRESHOLD {
+                *pixel = image::Rgba([0, 0, 0, 0]);
+            } else {
+                pixel[3] = u8::MAX;
+            }
+        }
+        self.encoder
+            .encode_frame(image::Frame::from_parts(img, 0, 0, self.delay))?;
+        Ok(())
+    }
+}
+
+/// Encodes the current sprite as a GIF looping forever, with one frame per animation frame.
+pub fn write_gif(
+    gfx: &mut Graphics,
+    player: &mut AnimationPlayer<NotanBackend>,
+    scale: f32,
+) -> anyhow::Result<Vec<u8>> {
+    let (inner, outer) = calculate_dimensions(player, scale);
+    let frame_rate = u32::from(player.animation().frame_rate.max(1));
+    let mut output = vec![];
+    let mut encoder = GifEncoder::new_with_speed(&mut output, GIF_SPEED);
+    encoder.set_repeat(Repeat::Infinite)?;
+    let mut writer = GifFrames {
+        encoder,
+        width: outer.width() as _,
+        height: outer.height() as _,
+        delay: image::Delay::from_numer_denom_ms(1000, frame_rate),
+    };
+    write_frames(gfx, player, &mut writer, scale, inner, outer)?;
+    drop(writer);
+    Ok(output)
+}
+
+struct ApngFrames<'a> {
+    writer: png::Writer<&'a mut Vec<u8>>,
+}
+
+impl FrameWriter for ApngFrames<'_> {
+    fn write_frame(&mut self, bytes: &[u8], _frame: usize) -> anyhow::Result<()> {
+        self.writer.write_image_data(bytes)?;
+        Ok(())
+    }
+}
+
+/// Encodes the current sprite as an APNG looping forever, with one frame per animation frame.
+pub fn write_apng(
+    gfx: &mut Graphics,
+    player: &mut AnimationPlayer<NotanBackend>,
+    scale: f32,
+) -> anyhow::Result<Vec<u8>> {
+    let (inner, outer) = calculate_dimensions(player, scale);
+    let frame_count = player.current_sprite().frame_count() as u32;
+    let frame_rate = u16::from(player.animation().frame_rate.max(1));
+    let mut output = vec![];
+    let mut encoder = png::Encoder::new(&mut output, outer.width() as _, outer.height() as _);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(frame_count, 0)?;
+    encoder.set_frame_delay(1, frame_rate)?;
+    let mut writer = ApngFrames {
+        writer: encoder.write_header()?,
+    };
+    write_frames(gfx, player, &mut writer, scale, inner, outer)?;
+    writer.writer.finish()?;
+    Ok(output)
+}
+
 #[derive(Debug)]
 struct SplitPngFrames {
     dir: PathBuf,
@@ -63,6 +150,143 @@ pub fn write_individual_frames(
     write_frames(gfx, player, &mut writer, scale, inner, outer)
 }
 
+#[derive(Debug)]
+struct CollectedFrames {
+    width: u32,
+    height: u32,
+    frames: Vec<image::RgbaImage>,
+}
+
+impl FrameWriter for CollectedFrames {
+    fn write_frame(&mut self, bytes: &[u8], _frame: usize) -> anyhow::Result<()> {
+        let img = image::RgbaImage::from_raw(self.width, self.height, bytes.to_vec())
+            .ok_or_else(|| anyhow::anyhow!("generated image was invalid"))?;
+        self.frames.push(img);
+        Ok(())
+    }
+}
+
+pub fn write_sprite_sheet(
+    gfx: &mut Graphics,
+    player: &mut AnimationPlayer<NotanBackend>,
+    scale: f32,
+    sprites: &[i16],
+    path: &Path,
+) -> anyhow::Result<()> {
+    let frame_duration = 1000 / u32::from(player.animation().frame_rate.max(1));
+    let mut animations = Vec::with_capacity(sprites.len());
+
+    for &id in sprites {
+        let (name, frames, pivot) = collect_sprite_frames(gfx, player, scale, id)?;
+        animations.push(SheetAnimation {
+            name,
+            frames,
+            pivot,
+            frame_duration,
+        });
+    }
+    sheet::write(&animations, path)
+}
+
+/// Writes the frames of every sprite to `dir` along with a Godot `SpriteFrames` resource.
+/// The resource refers to the frames as `res://{dir name}/...`, so the directory is expected
+/// to be placed at the root of the Godot project.
+pub fn write_godot_sprite_frames(
+    gfx: &mut Graphics,
+    player: &mut AnimationPlayer<NotanBackend>,
+    scale: f32,
+    sprites: &[i16],
+    dir: &Path,
+) -> anyhow::Result<()> {
+    let res_dir = dir
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow::anyhow!("invalid output directory: {}", dir.display()))?;
+    let mut sequences = Vec::with_capacity(sprites.len());
+
+    // frames are saved under the animation names, which tell apart sprites sharing a name
+    let animation = player.animation();
+    let named = sprites
+        .iter()
+        .map(|id| {
+            let name = animation
+                .sprites
+                .get(id)
+                .and_then(|sprite| sprite.name.name.clone())
+                .unwrap_or_else(|| id.to_string());
+            (*id, name)
+        })
+        .collect::<Vec<_>>();
+    let keys = godot::animation_names(
+        &named
+            .iter()
+            .map(|(id, name)| (*id, name.as_str()))
+            .collect::<Vec<_>>(),
+    );
+
+    for ((id, name), key) in named.into_iter().zip(keys) {
+        let (_, frames, _) = collect_sprite_frames(gfx, player, scale, id)?;
+        let mut paths = Vec::with_capacity(frames.len());
+        for (i, frame) in frames.iter().enumerate() {
+            let file_name = format!("{}_{}.png", frame_file_stem(&key, id), i);
+            frame.save(dir.join(&file_name))?;
+            paths.push(format!("res://{}/{}", res_dir, file_name));
+        }
+        sequences.push(godot::FrameSequence {
+            sprite: id,
+            name,
+            frames: paths,
+        });
+    }
+
+    let resource = godot::sprite_frames(&player.animation(), &sequences);
+    std::fs::write(dir.join("sprite_frames.tres"), resource)?;
+    Ok(())
+}
+
+/// The name frames of a sprite are saved under, which is the sprite name unless it has
+/// characters that are not safe in file names, in which case the sprite id is used.
+fn frame_file_stem(name: &str, id: i16) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.');
+    if !name.is_empty() && !name.starts_with('.') && name.chars().all(is_safe) {
+        name.to_owned()
+    } else {
+        id.to_string()
+    }
+}
+
+/// Renders every frame of a sprite, returning its name, the frames and the position of the
+/// sprite origin within them, normalized to the frame size.
+fn collect_sprite_frames(
+    gfx: &mut Graphics,
+    player: &mut AnimationPlayer<NotanBackend>,
+    scale: f32,
+    id: i16,
+) -> anyhow::Result<(String, Vec<image::RgbaImage>, (f32, f32))> {
+    player.set_sprite(id);
+    let (inner, outer) = calculate_dimensions(player, scale);
+    let mut writer = CollectedFrames {
+        width: outer.width() as _,
+        height: outer.height() as _,
+        frames: vec![],
+    };
+    write_frames(gfx, player, &mut writer, scale, inner, outer)?;
+
+    // the origin is placed in screen units, which the frame is scaled from, and the frame
+    // is flipped vertically when read back
+    let (origin_x, origin_y) = frame_origin(inner);
+    let (output_x, output_y) = gfx.size();
+    let pivot = (origin_x / output_x as f32, 1. - origin_y / output_y as f32);
+
+    let name = player
+        .current_sprite()
+        .name
+        .name
+        .clone()
+        .unwrap_or_else(|| id.to_string());
+    Ok((name, writer.frames, pivot))
+}
+
 fn write_frames(
     gfx: &mut Graphics,
     player: &mut AnimationPlayer<NotanBackend>,
@@ -85,9 +309,10 @@ fn write_frames(
     for i in 0..player.current_sprite().frame_count() {
         player.backend_mut().draw_mut().clear(Color::TRANSPARENT);
 
-        let translation =
-            SpriteTransform::translate((FRAME_PADDING - inner.min.x) * 2., FRAME_PADDING * 2.);
+        let (origin_x, origin_y) = frame_origin(inner);
+        let translation = SpriteTransform::translate(origin_x, origin_y);
         let scale = SpriteTransform::scale(output_ratio_x * scale, -output_ratio_y * scale);
+        player.set_frame(i as u32);
         player.render(scale.combine(&translation));
         gfx.render_to(&target, &player.backend_mut().swap(gfx.create_draw()));
 
@@ -99,6 +324,12 @@ fn write_frames(
     Ok(())
 }
 
+/// Where the sprite origin is drawn when rendering a frame, in screen units.
+#[inline]
+fn frame_origin(inner: Box2D<f32>) -> (f32, f32) {
+    ((FRAME_PADDING - inner.min.x) * 2., FRAME_PADDING * 2.)
+}
+
 fn calculate_dimensions(
     player: &AnimationPlayer<NotanBackend>,
     scale: f32,
@@ -0,0 +1,503 @@
+//! Extraction and packing of the shapes of a texture atlas.
+//!
+//! Atlases are stored upside down (see `flip_y` in the renderer backends), so parts are
+//! flipped when they are cut out and appear the way they are displayed in game. [`repack`]
+//! flips them back when building a new atlas.
+use std::io;
+
+use image::{RgbaImage, imageops};
+
+use crate::import::crc32;
+use crate::types::{Animation, Shape, Texture};
+
+/// A shape cut out of the atlas.
+#[derive(Debug, Clone)]
+pub struct Part {
+    pub id: i16,
+    pub image: RgbaImage,
+}
+
+/// Describes where the parts of an atlas came from.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Manifest {
+    /// Name of the texture the parts were cut from.
+    pub texture: Option<String>,
+    pub atlas_width: u32,
+    pub atlas_height: u32,
+    pub parts: Vec<PartInfo>,
+}
+
+/// Placement of a single part in the atlas and in the sprite.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PartInfo {
+    /// ID of the shape the part was cut from.
+    pub id: i16,
+    /// File name of the part image.
+    pub file: String,
+    /// Region of the part in the atlas, in pixels.
+    pub region: Region,
+    /// Size of the shape when rendered.
+    pub width: u16,
+    pub height: u16,
+    /// Offset of the shape when rendered.
+    pub offset_x: f32,
+    pub offset_y: f32,
+}
+
+/// A rectangle in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    /// Converts the UV rectangle of a shape to pixels, clamped to the atlas bounds.
+    pub fn of_shape(shape: &Shape, atlas_width: u32, atlas_height: u32) -> Region {
+        let (w, h) = (atlas_width as f32, atlas_height as f32);
+        let x = ((shape.left * w).round().max(0.) as u32).min(atlas_width);
+        let y = ((shape.top * h).round().max(0.) as u32).min(atlas_height);
+        let right = ((shape.right * w).round().max(0.) as u32).min(atlas_width);
+        let bottom = ((shape.bottom * h).round().max(0.) as u32).min(atlas_height);
+        Region {
+            x,
+            y,
+            width: right.saturating_sub(x),
+            height: bottom.saturating_sub(y),
+        }
+    }
+}
+
+/// Cuts every shape of the animation out of its atlas, ordered by shape ID.
+pub fn slice(animation: &Animation, atlas: &RgbaImage) -> (Vec<Part>, Manifest) {
+    let mut shapes = animation.shapes.values().collect::<Vec<_>>();
+    shapes.sort_unstable_by_key(|shape| shape.id);
+
+    let mut parts = Vec::with_capacity(shapes.len());
+    let mut infos = Vec::with_capacity(shapes.len());
+    for shape in shapes {
+        let region = Region::of_shape(shape, atlas.width(), atlas.height());
+        let image = imageops::crop_imm(atlas, region.x, region.y, region.width, region.height);
+        parts.push(Part {
+            id: shape.id,
+            image: imageops::flip_vertical(&*image),
+        });
+        infos.push(PartInfo {
+            id: shape.id,
+            file: part_file_name(shape.id),
+            region,
+            width: shape.width,
+            height: shape.height,
+            offset_x: shape.offset_x,
+            offset_y: shape.offset_y,
+        });
+    }
+
+    let manifest = Manifest {
+        texture: animation.texture.as_ref().map(|tex| tex.name.clone()),
+        atlas_width: atlas.width(),
+        atlas_height: atlas.height(),
+        parts: infos,
+    };
+    (parts, manifest)
+}
+
+/// Returns the file name used for the image of a shape.
+#[inline]
+pub fn part_file_name(id: i16) -> String {
+    format!("{}.png", id)
+}
+
+/// Options for [`repack`].
+#[derive(Debug, Clone, Copy)]
+pub struct PackOptions {
+    /// Whether to remove the transparent borders of parts, adjusting the shape offsets.
+    pub trim: bool,
+    /// Transparent pixels kept between parts, to avoid bleeding when filtering.
+    pub padding: u32,
+    /// Width of the atlas, grown if a single part is wider.
+    pub max_width: u32,
+}
+
+impl Default for PackOptions {
+    #[inline]
+    fn default() -> Self {
+        PackOptions {
+            trim: true,
+            padding: 1,
+            max_width: 2048,
+        }
+    }
+}
+
+/// An animation together with the parts to pack for its shapes.
+#[derive(Debug)]
+pub struct PackSource<'a> {
+    pub animation: &'a mut Animation,
+    pub parts: Vec<Part>,
+}
+
+/// Packs the parts of one or more animations into a single atlas named `texture` and rewrites
+/// the texture, and the UVs, sizes and offsets of the shapes of the animations to match it.
+/// Every shape needs a part with the same ID.
+///
+/// The size of a shape is scaled with its part, so parts can be redrawn at a higher
+/// resolution than the shape they replace.
+pub fn repack(
+    sources: &mut [PackSource<'_>],
+    texture: &str,
+    options: &PackOptions,
+) -> io::Result<RgbaImage> {
+    let mut images = vec![];
+    let mut entries = vec![];
+    for (source_index, source) in sources.iter().enumerate() {
+        let mut ids = source.animation.shapes.keys().copied().collect::<Vec<_>>();
+        ids.sort_unstable();
+        for id in ids {
+            let part = source
+                .parts
+                .iter()
+                .find(|part| part.id == id)
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("missing part for shape {}", id),
+                    )
+                })?;
+            let trimmed = if options.trim {
+                trim(&part.image)
+            } else {
+                Region {
+                    x: 0,
+                    y: 0,
+                    width: part.image.width(),
+                    height: part.image.height(),
+                }
+            };
+            let image = imageops::crop_imm(
+                &part.image,
+                trimmed.x,
+                trimmed.y,
+                trimmed.width,
+                trimmed.height,
+            )
+            .to_image();
+            entries.push((
+                source_index,
+                id,
+                part.image.width(),
+                part.image.height(),
+                trimmed,
+            ));
+            images.push(image);
+        }
+    }
+
+    let (atlas, regions) = pack(&images, options);
+    for ((source_index, id, source_width, source_height, trimmed), region) in
+        entries.into_iter().zip(regions)
+    {
+        let Some(shape) = sources[source_index].animation.shapes.get_mut(&id) else {
+            continue;
+        };
+        let scale_x = f32::from(shape.width) / source_width.max(1) as f32;
+        let scale_y = f32::from(shape.height) / source_height.max(1) as f32;
+        set_region(shape, region, atlas.width(), atlas.height());
+        shape.texture_index = 0;
+        shape.width = (trimmed.width as f32 * scale_x).round() as u16;
+        shape.height = (trimmed.height as f32 * scale_y).round() as u16;
+        shape.offset_x += trimmed.x as f32 * scale_x;
+        shape.offset_y += trimmed.y as f32 * scale_y;
+    }
+    for source in sources {
+        source.animation.texture = Some(Texture {
+            name: texture.to_owned(),
+            crc: crc32(texture),
+        });
+    }
+    Ok(atlas)
+}
+
+/// Packs images into rows of decreasing height and returns the atlas, stored upside down,
+/// with the region of every image as displayed.
+pub(crate) fn pack(images: &[RgbaImage], options: &PackOptions) -> (RgbaImage, Vec<Region>) {
+    let padding = options.padding;
+    let width = images
+        .iter()
+        .map(|image| image.width() + padding * 2)
+        .max()
+        .unwrap_or(1)
+        .max(options.max_width);
+
+    let mut order = (0..images.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| std::cmp::Reverse((images[i].height(), images[i].width())));
+
+    let mut regions = vec![
+        Region {
+            x: 0,
+            y: 0,
+            width: 0,
+            height: 0,
+        };
+        images.len()
+    ];
+    let (mut x, mut y, mut row_height) = (0, 0, 0);
+    for i in order {
+        let image = &images[i];
+        if x + image.width() + padding * 2 > width {
+            y += row_height;
+            x = 0;
+            row_height = 0;
+        }
+        regions[i] = Region {
+            x: x + padding,
+            y: y + padding,
+            width: image.width(),
+            height: image.height(),
+        };
+        x += image.width() + padding * 2;
+        row_height = row_height.max(image.height() + padding * 2);
+    }
+
+    let used_width = regions
+        .iter()
+        .map(|region| region.x + region.width + padding)
+        .max()
+        .unwrap_or(0);
+    let mut atlas = RgbaImage::new(used_width.max(1), (y + row_height).max(1));
+    for (image, region) in images.iter().zip(&regions) {
+        imageops::replace(&mut atlas, image, region.x.into(), region.y.into());
+    }
+    imageops::flip_vertical_in_place(&mut atlas);
+    (atlas, regions)
+}
+
+/// Points a shape at a region of an upside down atlas.
+pub(crate) fn set_region(shape: &mut Shape, region: Region, atlas_width: u32, atlas_height: u32) {
+    let (w, h) = (atlas_width as f32, atlas_height as f32);
+    let top = atlas_height - region.y - region.height;
+    shape.left = region.x as f32 / w;
+    shape.right = (region.x + region.width) as f32 / w;
+    shape.top = top as f32 / h;
+    shape.bottom = (top + region.height) as f32 / h;
+}
+
+/// Returns the bounds of the opaque pixels of an image, or a single pixel if there are none.
+pub fn trim(image: &RgbaImage) -> Region {
+    let mut min = (image.width(), image.height());
+    let mut max = (0, 0);
+    for (x, y, pixel) in image.enumerate_pixels() {
+        if pixel[3] != 0 {
+            min = (min.0.min(x), min.1.min(y));
+            max = (max.0.max(x + 1), max.1.max(y + 1));
+        }
+    }
+    if max.0 > min.0 && max.1 > min.1 {
+        Region {
+            x: min.0,
+            y: min.1,
+            width: max.0 - min.0,
+            height: max.1 - min.1,
+        }
+    } else {
+        Region {
+            x: 0,
+            y: 0,
+            width: image.width().min(1),
+            height: image.height().min(1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+    use crate::fixtures;
+
+    fn filled(width: u32, height: u32, value: u8) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, Rgba([value, value, value, 255]))
+    }
+
+    #[test]
+    fn packs_tallest_images_first() {
+        let images = [filled(2, 2, 1), filled(3, 4, 2), filled(4, 2, 3)];
+        let options = PackOptions {
+            padding: 1,
+            max_width: 10,
+            ..PackOptions::default()
+        };
+        let (atlas, regions) = pack(&images, &options);
+
+        assert_eq!(
+            regions,
+            [
+                Region {
+                    x: 7,
+                    y: 7,
+                    width: 2,
+                    height: 2,
+                },
+                Region {
+                    x: 1,
+                    y: 1,
+                    width: 3,
+                    height: 4,
+                },
+                Region {
+                    x: 1,
+                    y: 7,
+                    width: 4,
+                    height: 2,
+                },
+            ]
+        );
+        assert_eq!((atlas.width(), atlas.height()), (10, 10));
+    }
+
+    #[test]
+    fn stores_the_atlas_upside_down() {
+        let images = [filled(2, 2, 1), filled(3, 4, 2), filled(4, 2, 3)];
+        let (atlas, regions) = pack(&images, &PackOptions::default());
+
+        for (image, region) in images.iter().zip(&regions) {
+            let y = atlas.height() - region.y - region.height;
+            assert_eq!(atlas.get_pixel(region.x, y), image.get_pixel(0, 0));
+        }
+        assert_eq!(atlas.get_pixel(0, 0)[3], 0);
+    }
+
+    #[test]
+    fn grows_past_the_maximum_width_for_wide_images() {
+        let options = PackOptions {
+            padding: 2,
+            max_width: 4,
+            ..PackOptions::default()
+        };
+        let (atlas, regions) = pack(&[filled(6, 1, 1), filled(1, 1, 2)], &options);
+
+        assert_eq!(regions[0].x, 2);
+        assert_eq!((regions[1].x, regions[1].y), (2, 7));
+        assert_eq!((atlas.width(), atlas.height()), (10, 10));
+    }
+
+    #[test]
+    fn trims_transparent_borders() {
+        let mut image = RgbaImage::new(5, 4);
+        image.put_pixel(1, 2, Rgba([0, 0, 0, 255]));
+        image.put_pixel(3, 1, Rgba([0, 0, 0, 1]));
+
+        assert_eq!(
+            trim(&image),
+            Region {
+                x: 1,
+                y: 1,
+                width: 3,
+                height: 2,
+            }
+        );
+        assert_eq!(
+            trim(&RgbaImage::new(5, 4)),
+            Region {
+                x: 0,
+                y: 0,
+                width: 1,
+                height: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn slices_shapes_upside_down() {
+        let animation = fixtures::animation();
+        let mut atlas = RgbaImage::new(8, 4);
+        atlas.put_pixel(0, 0, Rgba([1, 1, 1, 255]));
+        atlas.put_pixel(0, 1, Rgba([2, 2, 2, 255]));
+        let (parts, manifest) = slice(&animation, &atlas);
+
+        assert_eq!(parts.iter().map(|part| part.id).collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(parts[0].image.get_pixel(0, 0), &Rgba([2, 2, 2, 255]));
+        assert_eq!(parts[0].image.get_pixel(0, 1), &Rgba([1, 1, 1, 255]));
+        assert_eq!(manifest.texture.as_deref(), Some("7001"));
+        assert_eq!(
+            manifest.parts[1],
+            PartInfo {
+                id: 2,
+                file: "2.png".to_owned(),
+                region: Region {
+                    x: 2,
+                    y: 0,
+                    width: 2,
+                    height: 2,
+                },
+                width: 16,
+                height: 32,
+                offset_x: -8.,
+                offset_y: -32.,
+            }
+        );
+    }
+
+    #[test]
+    fn repacks_animations_into_a_shared_atlas() {
+        let mut first = fixtures::animation();
+        let mut second = fixtures::animation();
+        second.texture = None;
+        // a part redrawn at twice the size of its shape, with a transparent border
+        let mut trimmed = RgbaImage::new(32, 64);
+        for y in 8..24 {
+            for x in 4..12 {
+                trimmed.put_pixel(x, y, Rgba([3, 3, 3, 255]));
+            }
+        }
+        let parts = || {
+            vec![
+                Part {
+                    id: 1,
+                    image: trimmed.clone(),
+                },
+                Part {
+                    id: 2,
+                    image: filled(16, 32, 4),
+                },
+            ]
+        };
+        let mut sources = [
+            PackSource {
+                animation: &mut first,
+                parts: parts(),
+            },
+            PackSource {
+                animation: &mut second,
+                parts: parts(),
+            },
+        ];
+        let atlas = repack(&mut sources, "packed", &PackOptions::default()).unwrap();
+
+        for animation in [&first, &second] {
+            let texture = animation.texture.as_ref().unwrap();
+            assert_eq!(texture.name, "packed");
+            assert_eq!(texture.crc, crc32("packed"));
+
+            let shape = &animation.shapes[&1];
+            assert_eq!(shape.texture_index, 0);
+            assert_eq!((shape.width, shape.height), (4, 8));
+            assert_eq!((shape.offset_x, shape.offset_y), (-6., -28.));
+            assert_eq!(
+                animation.shapes[&2].offset_x,
+                fixtures::animation().shapes[&2].offset_x
+            );
+
+            // slicing the new atlas gives back the trimmed parts
+            let (parts, _) = slice(animation, &atlas);
+            assert_eq!(parts[0].image, filled(8, 16, 3));
+            assert_eq!(parts[1].image, filled(16, 32, 4));
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! Recoloring of the parts of an animation, such as the skin, hair or clothing of characters.
+use hashbrown::HashMap;
+
+use crate::render::{ColorTransform, Render, SpriteTransform};
+use crate::types::{Shape, Sprite};
+
+/// Colors applied to every shape under the sprites with a given name CRC, as in
+/// [`SpriteName::name_crc`](crate::types::SpriteName).
+#[derive(Debug, Clone, Default)]
+pub struct ColorSlots {
+    slots: HashMap<i32, ColorTransform>,
+}
+
+impl ColorSlots {
+    #[inline]
+    pub fn new() -> ColorSlots {
+        ColorSlots::default()
+    }
+
+    #[inline]
+    pub fn set(&mut self, name_crc: i32, color: ColorTransform) {
+        self.slots.insert(name_crc, color);
+    }
+
+    #[inline]
+    pub fn get(&self, name_crc: i32) -> Option<&ColorTransform> {
+        self.slots.get(&name_crc)
+    }
+
+    #[inline]
+    pub fn remove(&mut self, name_crc: i32) -> Option<ColorTransform> {
+        self.slots.remove(&name_crc)
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.slots.clear();
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (i32, &ColorTransform)> {
+        self.slots.iter().map(|(&crc, color)| (crc, color))
+    }
+}
+
+/// A backend applying [`ColorSlots`] to the shapes it renders.
+///
+/// The color of a slot is composed like the color of a placement of the sprite, so it is
+/// applied before the colors of its ancestors, and nested slots apply on top of each other.
+#[derive(Debug)]
+pub struct Recolored<'a, R> {
+    backend: &'a mut R,
+    slots: &'a ColorSlots,
+}
+
+impl<'a, R: Render> Recolored<'a, R> {
+    #[inline]
+    pub fn new(backend: &'a mut R, slots: &'a ColorSlots) -> Self {
+        Self { backend, slots }
+    }
+}
+
+impl<R: Render> Render for Recolored<'_, R> {
+    #[inline]
+    fn render(&mut self, shape: &Shape, transform: SpriteTransform) {
+        self.backend.render(shape, transform);
+    }
+
+    fn sprite_transform(&mut self, sprite: &Sprite, transform: SpriteTransform) -> SpriteTransform {
+        let transform = match self.slots.get(sprite.name.name_crc) {
+            Some(color) => SpriteTransform {
+                color: color.clone(),
+                ..SpriteTransform::identity()
+            }
+            .combine(&transform),
+            None => transform,
+        };
+        self.backend.sprite_transform(sprite, transform)
+    }
+
+    #[inline]
+    fn set_atlas(&mut self, atlas: usize) {
+        self.backend.set_atlas(atlas);
+    }
+
+    #[inline]
+    fn enter(&mut self, id: i16) {
+        self.backend.enter(id);
+    }
+
+    #[inline]
+    fn leave(&mut self, id: i16) {
+        self.backend.leave(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::types::Color;
+
+    /// Records the channels of the colors of the shapes drawn.
+    #[derive(Debug, Default)]
+    struct Recorder {
+        colors: Vec<[f32; 4]>,
+    }
+
+    impl Render for Recorder {
+        fn render(&mut self, _shape: &Shape, transform: SpriteTransform) {
+            self.colors.push(channels(transform.color.into_color()));
+        }
+    }
+
+    #[inline]
+    fn channels(color: Color) -> [f32; 4] {
+        [color.red, color.green, color.blue, color.alpha]
+    }
+
+    fn rendered(slots: &ColorSlots, parent: SpriteTransform) -> Vec<[f32; 4]> {
+        let animation = fixtures::animation();
+        let mut recorder = Recorder::default();
+        let mut backend = Recolored::new(&mut recorder, slots);
+        backend.render_sprite(&animation, &animation.sprites[&10], parent, 0);
+        recorder.colors
+    }
+
+    #[test]
+    fn applies_slots_before_ancestor_colors() {
+        let mut slots = ColorSlots::new();
+        slots.set(1000, ColorTransform::Multiply(0.5, 0.5, 0.5, 0.5));
+        let parent = SpriteTransform::color_add(0.25, 0.25, 0.25, 0.);
+
+        let animation = fixtures::animation();
+        let mut recorder = Recorder::default();
+        let mut backend = Recolored::new(&mut recorder, &slots);
+        let transform = backend.sprite_transform(&animation.sprites[&10], parent.clone());
+        assert_eq!(
+            channels(transform.color.into_color()),
+            [0.75, 0.75, 0.75, 0.5]
+        );
+
+        // placements of the sprite are colored first, then the slot, then the ancestors
+        let plain = rendered(&ColorSlots::new(), SpriteTransform::identity());
+        let recolored = rendered(&slots, parent);
+        assert!(!plain.is_empty());
+        let expected = plain
+            .iter()
+            .map(|&[red, green, blue, alpha]| {
+                let color = ColorTransform::Multiply(0.5, 0.5, 0.5, 0.5)
+                    .combine(&ColorTransform::Add(0.25, 0.25, 0.25, 0.))
+                    .fold(Color::new(red, green, blue, alpha));
+                channels(color)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(recolored, expected);
+    }
+
+    #[test]
+    fn leaves_other_sprites_unchanged() {
+        let mut slots = ColorSlots::new();
+        slots.set(4200, ColorTransform::Multiply(0.5, 0.5, 0.5, 0.5));
+        assert_eq!(
+            rendered(&slots, SpriteTransform::identity()),
+            rendered(&ColorSlots::new(), SpriteTransform::identity())
+        );
+    }
+}
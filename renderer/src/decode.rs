@@ -1,3 +1,4 @@
+use std::fmt;
 use std::io;
 
 use byteorder::*;
@@ -41,6 +42,566 @@ pub trait DecodeExt: io::Read + Sized {
 
 impl<R: io::Read> DecodeExt for R {}
 
+/// Options for [`Decoder`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DecodeOptions {
+    /// Whether to skip over unknown actions, sprite tags and frame data tags, recording them as
+    /// warnings, instead of failing.
+    pub lenient: bool,
+    /// How to decode strings that are not valid UTF-8.
+    pub strings: StringEncoding,
+}
+
+/// How strings that are not valid UTF-8, found in some older assets, are decoded. Valid UTF-8
+/// is always decoded as such, and other strings are recorded as warnings unless decoding fails.
+///
+/// Strings decoded with a fallback are encoded back as UTF-8, so they no longer match the
+/// original bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StringEncoding {
+    /// Fail to decode the animation.
+    #[default]
+    Strict,
+    /// Replace invalid sequences with `U+FFFD`.
+    Lossy,
+    /// Decode every byte as a Latin-1 character.
+    Latin1,
+}
+
+/// A problem skipped over while decoding. Unknown version flags are recorded in any mode, the
+/// other problems only when decoding leniently.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeWarning {
+    /// Version flags without a known meaning.
+    UnknownVersionFlags(u8),
+    /// A version without the atlas flag, see [`AnimationVersion::use_atlas`]. The shapes are
+    /// decoded as if they were packed in an atlas.
+    NoAtlas,
+    /// An action with an unknown ID, decoded as [`Action::Unknown`] by reading its parameters
+    /// as strings. Parameters of other types are misread, so anything decoded after it may be
+    /// wrong.
+    UnknownAction { id: u8, param_count: u8 },
+    /// A random go to without any name, kept as an [`Action::Unknown`] without parameters.
+    EmptyRandomGoTo,
+    /// A sprite with an unknown tag. Its payload cannot be skipped, so the sprite and all the
+    /// following sprites and imports are missing.
+    UnknownSpriteTag {
+        sprite: i16,
+        tag: i8,
+        missing: usize,
+    },
+    /// A sprite whose frame data has an unknown tag, which was skipped along with the sprite.
+    UnknownFrameDataTag { sprite: i16, tag: u8 },
+    /// A string that is not valid UTF-8, as decoded with the [`StringEncoding`] fallback.
+    InvalidString(String),
+}
+
+impl fmt::Display for DecodeWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeWarning::UnknownVersionFlags(flags) => {
+                write!(f, "unknown version flags {:#04x}", flags)
+            }
+            DecodeWarning::NoAtlas => write!(f, "version does not use an atlas"),
+            DecodeWarning::UnknownAction { id, param_count } => write!(
+                f,
+                "unknown action {} with {} parameters read as strings, later data may be misread",
+                id, param_count
+            ),
+            DecodeWarning::EmptyRandomGoTo => write!(f, "random go to without any name"),
+            DecodeWarning::UnknownSpriteTag {
+                sprite,
+                tag,
+                missing,
+            } => write!(
+                f,
+                "unknown tag {} on sprite #{}, {} sprites were not decoded",
+                tag, sprite, missing
+            ),
+            DecodeWarning::InvalidString(string) => {
+                write!(f, "string {:?} is not valid UTF-8", string)
+            }
+            DecodeWarning::UnknownFrameDataTag { sprite, tag } => {
+                write!(
+                    f,
+                    "unknown frame data tag {} on sprite #{}, skipped",
+                    tag, sprite
+                )
+            }
+        }
+    }
+}
+
+/// Decodes animations with [`DecodeOptions`], collecting warnings.
+///
+/// The [`Decode`] implementations use a strict decoder, failing on the first unknown value.
+#[derive(Debug, Default)]
+pub struct Decoder {
+    options: DecodeOptions,
+    warnings: Vec<DecodeWarning>,
+}
+
+/// Result of decoding a single sprite.
+pub(crate) enum SpriteEntry {
+    Sprite(Sprite),
+    /// The sprite was skipped, the following ones can still be decoded.
+    Dropped,
+    /// The sprite could not be skipped, nothing after it can be decoded.
+    Unreadable,
+}
+
+impl Decoder {
+    #[inline]
+    pub fn new(options: DecodeOptions) -> Self {
+        Self {
+            options,
+            warnings: vec![],
+        }
+    }
+
+    /// The warnings collected so far.
+    #[inline]
+    pub fn warnings(&self) -> &[DecodeWarning] {
+        &self.warnings
+    }
+
+    #[inline]
+    pub(crate) fn push_warning(&mut self, warning: DecodeWarning) {
+        self.warnings.push(warning);
+    }
+
+    #[inline]
+    pub fn take_warnings(&mut self) -> Vec<DecodeWarning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    pub fn decode_animation<R: io::Read>(&mut self, cursor: &mut R) -> io::Result<Animation> {
+        let mut animation = self.decode_head(cursor)?;
+        let sprite_count = cursor.decode::<u16>()?;
+        let mut sprites = HashMap::with_capacity(sprite_count.into());
+        let mut sprite_order = Vec::with_capacity(sprite_count.into());
+        let mut truncated = false;
+        for index in 0..sprite_count {
+            match self.decode_sprite(cursor)? {
+                SpriteEntry::Sprite(sprite) => {
+                    sprite_order.push(sprite.id);
+                    sprites.insert(sprite.id, sprite);
+                }
+                SpriteEntry::Dropped => {}
+                SpriteEntry::Unreadable => {
+                    if let Some(DecodeWarning::UnknownSpriteTag { missing, .. }) =
+                        self.warnings.last_mut()
+                    {
+                        // sprites dropped earlier have warnings of their own
+                        *missing = usize::from(sprite_count - index);
+                    }
+                    truncated = true;
+                    break;
+                }
+            }
+        }
+        animation.sprites = sprites;
+        animation.sprite_order = sprite_order;
+        if !truncated {
+            animation.imports = self.decode_imports(cursor)?;
+        }
+        Ok(animation)
+    }
+
+    /// Decodes everything stored before the sprites, leaving the sprites and imports empty.
+    pub fn decode_head<R: io::Read>(&mut self, cursor: &mut R) -> io::Result<Animation> {
+        let version = cursor.decode::<AnimationVersion>()?;
+        self.check_version(&version)?;
+        let header = cursor.decode::<i16>()?;
+        let frame_rate = cursor.decode::<u8>()?;
+        let index = if version.use_local_index() {
+            Some(self.decode_index(cursor)?)
+        } else {
+            None
+        };
+        let texture_count = cursor.decode::<u16>()?;
+        if texture_count > 1 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported texture count: {}", texture_count),
+            ));
+        }
+        let texture = if texture_count == 1 {
+            Some(self.decode_texture(cursor)?)
+        } else {
+            None
+        };
+        let shapes = cursor.decode_prefixed::<u16, Shape>()?;
+        let shape_order = shapes.iter().map(|shape| shape.id).collect();
+        let shapes = shapes.into_iter().map(|shape| (shape.id, shape)).collect();
+        let transform = if version.use_transform_index() {
+            Some(self.decode_transform_table(cursor)?)
+        } else {
+            None
+        };
+        Ok(Animation {
+            version,
+            header,
+            frame_rate,
+            index,
+            texture,
+            shapes,
+            transform,
+            sprites: HashMap::new(),
+            imports: vec![],
+            shape_order,
+            sprite_order: vec![],
+        })
+    }
+
+    pub(crate) fn decode_imports<R: io::Read>(
+        &mut self,
+        cursor: &mut R,
+    ) -> io::Result<Vec<Import>> {
+        let count = cursor.decode::<u16>()?;
+        (0..count).map(|_| self.decode_import(cursor)).collect()
+    }
+
+    /// Decodes a null-terminated string with the [`StringEncoding`] of the options.
+    pub fn decode_string<R: io::Read>(&mut self, cursor: &mut R) -> io::Result<String> {
+        let buf = read_raw_string(cursor)?;
+        match String::from_utf8(buf) {
+            Ok(string) => Ok(string),
+            Err(err) => {
+                let string = match self.options.strings {
+                    StringEncoding::Strict => return Err(io::Error::other(err.to_string())),
+                    StringEncoding::Lossy => String::from_utf8_lossy(err.as_bytes()).into_owned(),
+                    StringEncoding::Latin1 => {
+                        err.as_bytes().iter().map(|&b| char::from(b)).collect()
+                    }
+                };
+                self.warnings
+                    .push(DecodeWarning::InvalidString(string.clone()));
+                Ok(string)
+            }
+        }
+    }
+
+    fn decode_texture<R: io::Read>(&mut self, cursor: &mut R) -> io::Result<Texture> {
+        let name = self.decode_string(cursor)?;
+        let crc = cursor.decode::<i32>()?;
+        Ok(Texture { name, crc })
+    }
+
+    fn decode_index<R: io::Read>(&mut self, cursor: &mut R) -> io::Result<AnimationIndex> {
+        let flags = cursor.decode::<AnimationFlags>()?;
+        let scale = cursor.decode_opt(flags.has_scale())?;
+        let render_radius = cursor.decode_opt(flags.has_render_radius())?;
+        let file_names = if flags.has_extension() {
+            let count = cursor.decode::<u16>()?;
+            Some(
+                (0..count)
+                    .map(|_| self.decode_string(cursor))
+                    .collect::<io::Result<_>>()?,
+            )
+        } else {
+            None
+        };
+        let parts_hidden_by = if flags.has_hiding_part() {
+            Some(cursor.decode_prefixed::<u8, HideablePart>()?)
+        } else {
+            None
+        };
+        let parts_to_be_hidden = if flags.can_hide_part() {
+            let count = cursor.decode::<u8>()?;
+            Some(
+                (0..count)
+                    .map(|_| self.decode_hidden_part(cursor))
+                    .collect::<io::Result<_>>()?,
+            )
+        } else {
+            None
+        };
+        let extension = cursor.decode_opt::<AnimationExtension>(flags.is_extended())?;
+        let count = cursor.decode::<u16>()?;
+        let animation_files = (0..count)
+            .map(|_| self.decode_animation_file(cursor))
+            .collect::<io::Result<_>>()?;
+        Ok(AnimationIndex {
+            flags,
+            scale,
+            render_radius,
+            file_names,
+            animation_files,
+            parts_to_be_hidden,
+            parts_hidden_by,
+            extension,
+        })
+    }
+
+    fn decode_hidden_part<R: io::Read>(&mut self, cursor: &mut R) -> io::Result<HiddenPart> {
+        let item_name = self.decode_string(cursor)?;
+        let crc_key = cursor.decode::<i32>()?;
+        Ok(HiddenPart { item_name, crc_key })
+    }
+
+    fn decode_animation_file<R: io::Read>(&mut self, cursor: &mut R) -> io::Result<AnimationFile> {
+        let name = self.decode_string(cursor)?;
+        let crc = cursor.decode::<i32>()?;
+        let file_index = cursor.decode::<i16>()?;
+        Ok(AnimationFile {
+            name,
+            crc,
+            file_index,
+        })
+    }
+
+    fn decode_import<R: io::Read>(&mut self, cursor: &mut R) -> io::Result<Import> {
+        let id = cursor.decode::<i16>()?;
+        let name = self.decode_string(cursor)?;
+        let crc = cursor.decode::<i32>()?;
+        Ok(Import { id, name, crc })
+    }
+
+    /// Records version flags without a known meaning, which are decoded as if they were not
+    /// set. Animations without an atlas are only decoded leniently, see
+    /// [`AnimationVersion::use_atlas`].
+    fn check_version(&mut self, version: &AnimationVersion) -> io::Result<()> {
+        let unknown = version.unknown_flags();
+        if unknown != 0 {
+            self.warnings
+                .push(DecodeWarning::UnknownVersionFlags(unknown));
+        }
+        if !version.use_atlas() {
+            if !self.options.lenient {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    DecodeWarning::NoAtlas.to_string(),
+                ));
+            }
+            self.warnings.push(DecodeWarning::NoAtlas);
+        }
+        Ok(())
+    }
+
+    pub fn decode_transform_table<R: io::Read>(
+        &mut self,
+        cursor: &mut R,
+    ) -> io::Result<TransformTable> {
+        let colors = cursor.decode_prefixed::<u32, f32>()?;
+        let rotations = cursor.decode_prefixed::<u32, f32>()?;
+        let translations = cursor.decode_prefixed::<u32, f32>()?;
+        let count = cursor.decode::<u32>()?;
+        let mut actions = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            actions.push(self.decode_action(cursor)?);
+        }
+        Ok(TransformTable {
+            colors,
+            rotations,
+            translations,
+            actions,
+        })
+    }
+
+    pub(crate) fn decode_sprite<R: io::Read>(&mut self, cursor: &mut R) -> io::Result<SpriteEntry> {
+        let (tag, id, flags, sprite_name) = self.decode_sprite_header(cursor)?;
+        self.decode_sprite_body(cursor, tag, id, flags, sprite_name)
+    }
+
+    /// Decodes the payload and frame data following the header of a sprite.
+    pub(crate) fn decode_sprite_body<R: io::Read>(
+        &mut self,
+        cursor: &mut R,
+        tag: i8,
+        id: i16,
+        flags: SpriteFlags,
+        sprite_name: SpriteName,
+    ) -> io::Result<SpriteEntry> {
+        let payload = match tag {
+            1 => {
+                let sprite_id = cursor.decode::<i16>()?;
+                let action_info = cursor.decode_prefixed::<u16, i16>()?;
+                SpritePayload::Single(sprite_id, action_info)
+            }
+            2 => SpritePayload::SingleNoAction(cursor.decode()?),
+            3 => {
+                let sprite_ids = cursor.decode_prefixed::<u16, i16>()?;
+                let action_info = cursor.decode_prefixed::<u16, i16>()?;
+                SpritePayload::SingleFrame(sprite_ids, action_info)
+            }
+            4 => {
+                let frame_pos = cursor.decode_prefixed::<u16, i32>()?;
+                let sprite_ids = cursor.decode_prefixed::<u16, i16>()?;
+                let action_info = cursor.decode_prefixed::<u16, i16>()?;
+                SpritePayload::Indexed(frame_pos, sprite_ids, action_info)
+            }
+            other if self.options.lenient => {
+                self.warnings.push(DecodeWarning::UnknownSpriteTag {
+                    sprite: id,
+                    tag: other,
+                    missing: 0,
+                });
+                return Ok(SpriteEntry::Unreadable);
+            }
+            other => return Err(io::Error::other(format!("Unexpected case: {}", other))),
+        };
+        let Some(frame_data) = self.decode_frame_data(cursor, id)? else {
+            return Ok(SpriteEntry::Dropped);
+        };
+        Ok(SpriteEntry::Sprite(Sprite {
+            id,
+            name: sprite_name,
+            flags,
+            frame_data,
+            payload,
+        }))
+    }
+
+    /// Decodes the tag, ID, flags and name a sprite starts with.
+    pub(crate) fn decode_sprite_header<R: io::Read>(
+        &mut self,
+        cursor: &mut R,
+    ) -> io::Result<(i8, i16, SpriteFlags, SpriteName)> {
+        let tag = cursor.decode::<i8>()?;
+        let id = cursor.decode::<i16>()?;
+        let flags = cursor.decode::<SpriteFlags>()?;
+        let name = if flags.has_name() {
+            Some(self.decode_string(cursor)?)
+        } else {
+            None
+        };
+        let name_crc = cursor.decode::<i32>()?;
+        let base_name_crc = cursor.decode::<i32>()?;
+        let name = SpriteName {
+            name,
+            name_crc,
+            base_name_crc,
+        };
+        Ok((tag, id, flags, name))
+    }
+
+    /// Decodes the frame data of a sprite, or skips it if its tag is unknown in lenient mode.
+    fn decode_frame_data<R: io::Read>(
+        &mut self,
+        cursor: &mut R,
+        sprite: i16,
+    ) -> io::Result<Option<FrameData>> {
+        let tag = cursor.decode::<u8>()?;
+        let size = cursor.decode::<u32>()? as usize;
+        match tag {
+            1 => {
+                let mut buf = vec![0; size];
+                cursor.read_exact(&mut buf)?;
+                Ok(Some(FrameData::Bytes(buf)))
+            }
+            2 => Ok(Some(FrameData::Shorts(cursor.decode_n(size)?))),
+            4 => Ok(Some(FrameData::Ints(cursor.decode_n(size)?))),
+            other if self.options.lenient => {
+                // the tag is the size of the values in bytes for the known ones
+                let len = size as u64 * u64::from(other);
+                let skipped = io::copy(&mut io::Read::take(&mut *cursor, len), &mut io::sink())?;
+                if skipped < len {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                self.warnings
+                    .push(DecodeWarning::UnknownFrameDataTag { sprite, tag: other });
+                Ok(None)
+            }
+            other => Err(io::Error::other(format!("Unexpected case: {}", other))),
+        }
+    }
+
+    pub fn decode_action<R: io::Read>(&mut self, cursor: &mut R) -> io::Result<Action> {
+        let id = cursor.decode::<u8>()?;
+        let param_count = cursor.decode::<u8>()?;
+        match id {
+            1 => {
+                let name = self.decode_string(cursor)?;
+                let percent = cursor.decode_opt::<u8>(param_count == 2)?;
+                Ok(Action::GoTo(name, percent))
+            }
+            2 => Ok(Action::GoToStatic),
+            3 => Ok(Action::RunScript(self.decode_string(cursor)?)),
+            4 if param_count == 0 => {
+                if !self.options.lenient {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "random go to without any name",
+                    ));
+                }
+                self.warnings.push(DecodeWarning::EmptyRandomGoTo);
+                Ok(Action::Unknown {
+                    id,
+                    raw_params: vec![],
+                })
+            }
+            4 => {
+                let first = self.decode_string(cursor)?;
+                if &first == "#optimized" {
+                    let count = (param_count - 1) / 2;
+                    let mut names = vec![first];
+                    for _ in 0..count {
+                        names.push(self.decode_string(cursor)?);
+                    }
+                    let percents = cursor.decode_n::<u8>(count.into())?;
+                    Ok(Action::GoToRandom(names, percents))
+                } else {
+                    // without the marker, the first parameter is already one of the names
+                    let mut names = vec![first];
+                    for _ in 1..param_count {
+                        names.push(self.decode_string(cursor)?);
+                    }
+                    Ok(Action::GoToRandom(names, vec![]))
+                }
+            }
+            5 => Ok(Action::Hit),
+            6 => Ok(Action::Delete),
+            7 => Ok(Action::End),
+            8 => {
+                // pairs of previous and next names, followed by an optional default
+                let count = param_count / 2;
+                let mut previous = Vec::with_capacity(count.into());
+                let mut next = Vec::with_capacity(count.into());
+                for _ in 0..count {
+                    previous.push(self.decode_string(cursor)?);
+                    next.push(self.decode_string(cursor)?);
+                }
+                let default = if param_count % 2 == 1 {
+                    Some(self.decode_string(cursor)?)
+                } else {
+                    None
+                };
+                Ok(Action::GoToIfPrevious(previous, next, default))
+            }
+            9 => {
+                let particle_id = cursor.decode::<i32>()?;
+                let offset_x = cursor.decode_opt::<i16>(param_count > 1)?;
+                let offset_y = cursor.decode_opt::<i16>(param_count > 2)?;
+                let offset_z = cursor.decode_opt::<i16>(param_count > 3)?;
+                Ok(Action::AddParticle(
+                    particle_id,
+                    offset_x,
+                    offset_y,
+                    offset_z,
+                ))
+            }
+            10 => Ok(Action::SetRadius(cursor.decode()?)),
+            other if self.options.lenient => {
+                // every parameter of variable size in known actions is a string, but this is a
+                // guess that can desynchronize the rest of the stream, so it is always reported
+                self.warnings.push(DecodeWarning::UnknownAction {
+                    id: other,
+                    param_count,
+                });
+                let raw_params = (0..param_count)
+                    .map(|_| read_raw_string(cursor))
+                    .collect::<io::Result<Vec<_>>>()?;
+                Ok(Action::Unknown {
+                    id: other,
+                    raw_params,
+                })
+            }
+            other => Err(io::Error::other(format!("Unexpected case: {}", other))),
+        }
+    }
+}
+
 impl<A: Decode, B: Decode> Decode for (A, B) {
     #[inline]
     fn decode<R: io::Read>(cursor: &mut R) -> io::Result<Self> {
@@ -105,47 +666,27 @@ impl Decode for f64 {
 }
 
 impl Decode for String {
+    #[inline]
     fn decode<R: io::Read>(cursor: &mut R) -> io::Result<Self> {
-        let mut buf = Vec::new();
-        let mut c = cursor.read_u8()?;
-        while c != 0 {
-            buf.push(c);
-            c = cursor.read_u8()?;
-        }
-        String::from_utf8(buf).map_err(|err| io::Error::other(err.to_string()))
+        Decoder::default().decode_string(cursor)
+    }
+}
+
+/// Reads the bytes of a null-terminated string, without the terminator.
+fn read_raw_string<R: io::Read>(cursor: &mut R) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    let mut c = cursor.read_u8()?;
+    while c != 0 {
+        buf.push(c);
+        c = cursor.read_u8()?;
     }
+    Ok(buf)
 }
 
 impl Decode for Animation {
+    #[inline]
     fn decode<R: io::Read>(cursor: &mut R) -> io::Result<Self> {
-        let version = cursor.decode::<AnimationVersion>()?;
-        cursor.decode::<i16>()?;
-        let frame_rate = cursor.decode::<u8>()?;
-        let index = cursor.decode_opt::<AnimationIndex>(version.use_local_index())?;
-        let texture_count = cursor.decode::<u16>()?;
-        let texture = cursor.decode_opt::<Texture>(texture_count == 1)?;
-        let shapes = cursor
-            .decode_prefixed::<u16, Shape>()?
-            .iter()
-            .map(move |shape| (shape.id, shape.clone()))
-            .collect();
-        let transform = cursor.decode_opt::<TransformTable>(version.use_transform_index())?;
-        let sprites_vec = cursor.decode_prefixed::<u16, Sprite>()?;
-        let sprites = sprites_vec
-            .iter()
-            .map(move |sprite| (sprite.id, sprite.clone()))
-            .collect();
-        let imports = cursor.decode_prefixed::<u16, Import>()?;
-        Ok(Animation {
-            version,
-            frame_rate,
-            index,
-            texture,
-            shapes,
-            transform,
-            sprites,
-            imports,
-        })
+        Decoder::default().decode_animation(cursor)
     }
 }
 
@@ -157,10 +698,9 @@ impl Decode for AnimationVersion {
 }
 
 impl Decode for Texture {
+    #[inline]
     fn decode<R: io::Read>(cursor: &mut R) -> io::Result<Self> {
-        let name = cursor.decode::<String>()?;
-        let crc = cursor.decode::<i32>()?;
-        Ok(Texture { name, crc })
+        Decoder::default().decode_texture(cursor)
     }
 }
 
@@ -192,63 +732,18 @@ impl Decode for Shape {
 }
 
 impl Decode for TransformTable {
+    #[inline]
     fn decode<R: io::Read>(cursor: &mut R) -> io::Result<Self> {
-        let colors = cursor.decode_prefixed::<u32, f32>()?;
-        let rotations = cursor.decode_prefixed::<u32, f32>()?;
-        let translations = cursor.decode_prefixed::<u32, f32>()?;
-        let actions = cursor.decode_prefixed::<u32, Action>()?;
-        Ok(TransformTable {
-            colors,
-            rotations,
-            translations,
-            actions,
-        })
+        Decoder::default().decode_transform_table(cursor)
     }
 }
 
 impl Decode for Sprite {
     fn decode<R: io::Read>(cursor: &mut R) -> io::Result<Self> {
-        let tag = cursor.decode::<i8>()?;
-        let id = cursor.decode::<i16>()?;
-        let flags = cursor.decode::<SpriteFlags>()?;
-        let name = cursor.decode_opt::<String>(flags.has_name())?;
-        let name_crc = cursor.decode::<i32>()?;
-        let base_name_crc = cursor.decode::<i32>()?;
-        let sprite_name = SpriteName {
-            name,
-            name_crc,
-            base_name_crc,
-        };
-        let payload = match tag {
-            1 => {
-                let sprite_id = cursor.decode::<i16>()?;
-                let action_info = cursor.decode_prefixed::<u16, i16>()?;
-                Ok(SpritePayload::Single(sprite_id, action_info))
-            }
-            2 => Ok(SpritePayload::SingleNoAction(cursor.decode()?)),
-            3 => {
-                let sprite_ids = cursor.decode_prefixed::<u16, i16>()?;
-                let action_info = cursor.decode_prefixed::<u16, i16>()?;
-                Ok(SpritePayload::SingleFrame(sprite_ids, action_info))
-            }
-            4 => {
-                let frame_pos = cursor.decode_prefixed::<u16, i32>()?;
-                let sprite_ids = cursor.decode_prefixed::<u16, i16>()?;
-                let action_info = cursor.decode_prefixed::<u16, i16>()?;
-                Ok(SpritePayload::Indexed(frame_pos, sprite_ids, action_info))
-            }
-            other => Err(io::Error::other(
-                format!("Unexpected case: {}", other),
-            )),
-        };
-        let frame_data = cursor.decode::<FrameData>()?;
-        Ok(Sprite {
-            id,
-            name: sprite_name,
-            flags,
-            frame_data,
-            payload: payload?,
-        })
+        match Decoder::default().decode_sprite(cursor)? {
+            SpriteEntry::Sprite(sprite) => Ok(sprite),
+            SpriteEntry::Dropped | SpriteEntry::Unreadable => unreachable!("strict decoding"),
+        }
     }
 }
 
@@ -261,55 +756,16 @@ impl Decode for SpriteFlags {
 
 impl Decode for FrameData {
     fn decode<R: io::Read>(cursor: &mut R) -> io::Result<Self> {
-        let tag = cursor.decode::<u8>()?;
-        let size = cursor.decode::<u32>()? as usize;
-        match tag {
-            1 => {
-                let mut buf = vec![0; size];
-                cursor.read_exact(&mut buf)?;
-                Ok(FrameData::Bytes(buf))
-            }
-            2 => Ok(FrameData::Shorts(cursor.decode_n(size)?)),
-            4 => Ok(FrameData::Ints(cursor.decode_n(size)?)),
-            other => Err(io::Error::other(
-                format!("Unexpected case: {}", other),
-            )),
-        }
+        Decoder::default()
+            .decode_frame_data(cursor, 0)
+            .map(|data| data.expect("strict decoding"))
     }
 }
 
 impl Decode for AnimationIndex {
+    #[inline]
     fn decode<R: io::Read>(cursor: &mut R) -> io::Result<Self> {
-        let flags = cursor.decode::<AnimationFlags>()?;
-        let scale = cursor.decode_opt(flags.has_scale())?;
-        let render_radius = cursor.decode_opt(flags.has_render_radius())?;
-        let file_names = if flags.has_extension() {
-            Some(cursor.decode_prefixed::<u16, String>()?)
-        } else {
-            None
-        };
-        let parts_hidden_by = if flags.has_hiding_part() {
-            Some(cursor.decode_prefixed::<u8, HideablePart>()?)
-        } else {
-            None
-        };
-        let parts_to_be_hidden = if flags.can_hide_part() {
-            Some(cursor.decode_prefixed::<u8, HiddenPart>()?)
-        } else {
-            None
-        };
-        let extension = cursor.decode_opt::<AnimationExtension>(flags.is_extended())?;
-        let animation_files = cursor.decode_prefixed::<u16, AnimationFile>()?;
-        Ok(AnimationIndex {
-            flags,
-            scale,
-            render_radius,
-            file_names,
-            animation_files,
-            parts_to_be_hidden,
-            parts_hidden_by,
-            extension,
-        })
+        Decoder::default().decode_index(cursor)
     }
 }
 
@@ -332,10 +788,9 @@ impl Decode for HideablePart {
 }
 
 impl Decode for HiddenPart {
+    #[inline]
     fn decode<R: io::Read>(cursor: &mut R) -> io::Result<Self> {
-        let item_name = cursor.decode::<String>()?;
-        let crc_key = cursor.decode::<i32>()?;
-        Ok(HiddenPart { item_name, crc_key })
+        Decoder::default().decode_hidden_part(cursor)
     }
 }
 
@@ -356,6 +811,7 @@ impl Decode for AnimationExtension {
         };
         let highlight_color = cursor.decode_opt(flags & 0x2 == 0x2)?;
         Ok(AnimationExtension {
+            flags,
             heights,
             highlight_color,
         })
@@ -377,84 +833,195 @@ impl Decode for Color {
 }
 
 impl Decode for AnimationFile {
+    #[inline]
     fn decode<R: io::Read>(cursor: &mut R) -> io::Result<Self> {
-        let name = cursor.decode::<String>()?;
-        let crc = cursor.decode::<i32>()?;
-        let file_index = cursor.decode::<i16>()?;
-        Ok(AnimationFile {
-            name,
-            crc,
-            file_index,
-        })
+        Decoder::default().decode_animation_file(cursor)
     }
 }
 
 impl Decode for Action {
+    #[inline]
     fn decode<R: io::Read + Sized>(cursor: &mut R) -> io::Result<Self> {
-        let id = cursor.decode::<u8>()?;
-        let param_count = cursor.decode::<u8>()?;
-        match id {
-            1 => {
-                let name = cursor.decode::<String>()?;
-                let percent = cursor.decode_opt::<u8>(param_count == 2)?;
-                Ok(Action::GoTo(name, percent))
-            }
-            2 => Ok(Action::GoToStatic),
-            3 => Ok(Action::RunScript(cursor.decode::<String>()?)),
-            4 => {
-                let first = cursor.decode::<String>()?;
-                if &first == "#optimized" {
-                    let count = (param_count - 1) / 2;
-                    let mut names = vec![first];
-                    for _ in 0..count {
-                        names.push(cursor.decode()?);
-                    }
-                    let percents = cursor.decode_n::<u8>(count.into())?;
-                    Ok(Action::GoToRandom(names, percents))
-                } else {
-                    let names = cursor.decode_n::<String>((param_count - 1).into())?;
-                    Ok(Action::GoToRandom(names, vec![]))
-                }
-            }
-            5 => Ok(Action::Hit),
-            6 => Ok(Action::Delete),
-            7 => Ok(Action::End),
-            8 => {
-                let count = (param_count - 1) / 2;
-                let mut previous = Vec::with_capacity(count.into());
-                let mut next = Vec::with_capacity(count.into());
-                for _ in 0..count {
-                    previous.push(cursor.decode()?);
-                    next.push(cursor.decode()?);
-                }
-                let default = cursor.decode_opt::<String>(param_count % 2 == 1)?;
-                Ok(Action::GoToIfPrevious(previous, next, default))
-            }
-            9 => {
-                let particle_id = cursor.decode::<i32>()?;
-                let offset_x = cursor.decode_opt::<i16>(param_count > 1)?;
-                let offset_y = cursor.decode_opt::<i16>(param_count > 2)?;
-                let offset_z = cursor.decode_opt::<i16>(param_count > 3)?;
-                Ok(Action::AddParticle(
-                    particle_id,
-                    offset_x,
-                    offset_y,
-                    offset_z,
-                ))
-            }
-            10 => Ok(Action::SetRadius(cursor.decode()?)),
-            other => Err(io::Error::other(
-                format!("Unexpected case: {}", other),
-            )),
-        }
+        Decoder::default().decode_action(cursor)
     }
 }
 
 impl Decode for Import {
+    #[inline]
     fn decode<R: io::Read>(cursor: &mut R) -> io::Result<Self> {
-        let id = cursor.decode::<i16>()?;
-        let name = cursor.decode::<String>()?;
-        let crc = cursor.decode::<i32>()?;
-        Ok(Import { id, name, crc })
+        Decoder::default().decode_import(cursor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode::{Encode, EncodeExt};
+    use crate::fixtures;
+
+    fn action(bytes: &[u8]) -> Action {
+        Action::decode(&mut io::Cursor::new(bytes)).unwrap()
+    }
+
+    fn decode_with_version(version: u8) -> (Animation, Vec<DecodeWarning>) {
+        let mut animation = fixtures::animation();
+        animation.version = AnimationVersion(version);
+        let mut bytes = vec![];
+        animation.encode(&mut bytes).unwrap();
+
+        let mut decoder = Decoder::default();
+        let decoded = decoder.decode_animation(&mut bytes.as_slice()).unwrap();
+        (decoded, decoder.take_warnings())
+    }
+
+    fn lenient() -> Decoder {
+        Decoder::new(DecodeOptions {
+            lenient: true,
+            ..DecodeOptions::default()
+        })
+    }
+
+    #[test]
+    fn counts_missing_sprites_from_the_unreadable_one() {
+        let animation = fixtures::animation();
+        let mut bytes = vec![];
+        animation.encode(&mut bytes).unwrap();
+        let mut cursor = io::Cursor::new(bytes.as_slice());
+        Decoder::default().decode_head(&mut cursor).unwrap();
+        let mut bytes = bytes[..cursor.position() as usize].to_vec();
+
+        let sprite = |id: i16| {
+            let mut bytes = vec![];
+            animation.sprites[&id].encode(&mut bytes).unwrap();
+            bytes
+        };
+        bytes.encode(&3u16).unwrap();
+        // a frame data tag of 3 bytes per value, skipped along with its sprite
+        let mut dropped = sprite(11);
+        let tag = dropped.len() - 6;
+        dropped[tag] = 3;
+        bytes.extend(dropped);
+        bytes.extend([0, 0]);
+        // an unknown sprite tag, after which nothing can be read
+        let mut unreadable = sprite(10);
+        unreadable[0] = 9;
+        bytes.extend(unreadable);
+        bytes.extend(sprite(12));
+
+        let mut decoder = lenient();
+        let decoded = decoder.decode_animation(&mut bytes.as_slice()).unwrap();
+        assert!(decoded.sprites.is_empty());
+        assert_eq!(
+            decoder.warnings(),
+            [
+                DecodeWarning::UnknownFrameDataTag { sprite: 11, tag: 3 },
+                DecodeWarning::UnknownSpriteTag {
+                    sprite: 10,
+                    tag: 9,
+                    missing: 2,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_unknown_actions_even_when_misread() {
+        let mut decoder = lenient();
+        let action = decoder.decode_action(&mut &b"\x0b\x01abc\0"[..]).unwrap();
+        assert_eq!(
+            action,
+            Action::Unknown {
+                id: 11,
+                raw_params: vec![b"abc".to_vec()],
+            }
+        );
+
+        let mut decoder = lenient();
+        assert!(decoder.decode_action(&mut &b"\x0b\x02abc\0"[..]).is_err());
+        assert_eq!(
+            decoder.warnings(),
+            [DecodeWarning::UnknownAction {
+                id: 11,
+                param_count: 2,
+            }]
+        );
+    }
+
+    #[test]
+    fn rejects_random_go_to_without_names() {
+        let err = Action::decode(&mut &b"\x04\x00"[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut decoder = lenient();
+        let action = decoder.decode_action(&mut &b"\x04\x00"[..]).unwrap();
+        assert_eq!(
+            action,
+            Action::Unknown {
+                id: 4,
+                raw_params: vec![],
+            }
+        );
+        assert_eq!(decoder.warnings(), [DecodeWarning::EmptyRandomGoTo]);
+
+        let mut bytes = vec![];
+        action.encode(&mut bytes).unwrap();
+        assert_eq!(bytes, b"\x04\x00");
+    }
+
+    #[test]
+    fn reports_unknown_version_flags_when_strict() {
+        let (animation, warnings) = decode_with_version(0x9B);
+        assert_eq!(animation.version.unknown_flags(), 0x80);
+        assert_eq!(warnings, [DecodeWarning::UnknownVersionFlags(0x80)]);
+    }
+
+    #[test]
+    fn reports_animations_without_an_atlas() {
+        let mut animation = fixtures::animation();
+        animation.version = AnimationVersion(0x1A);
+        let mut bytes = vec![];
+        animation.encode(&mut bytes).unwrap();
+
+        let err = Decoder::default()
+            .decode_animation(&mut bytes.as_slice())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut decoder = lenient();
+        let decoded = decoder.decode_animation(&mut bytes.as_slice()).unwrap();
+        assert!(!decoded.version.use_atlas());
+        assert_eq!(decoder.warnings(), [DecodeWarning::NoAtlas]);
+    }
+
+    #[test]
+    fn keeps_the_first_name_of_unoptimized_random_go_to() {
+        let action = action(b"\x04\x02a\0b\0");
+        let names = vec!["a".to_owned(), "b".to_owned()];
+        assert_eq!(action, Action::GoToRandom(names, vec![]));
+    }
+
+    #[test]
+    fn decodes_optimized_random_go_to() {
+        let action = action(b"\x04\x05#optimized\0a\0b\0\x1e\x46");
+        let names = vec!["#optimized".to_owned(), "a".to_owned(), "b".to_owned()];
+        assert_eq!(action, Action::GoToRandom(names, vec![30, 70]));
+    }
+
+    #[test]
+    fn decodes_go_to_if_previous_without_default() {
+        let action = action(b"\x08\x02a\0b\0");
+        let expected = Action::GoToIfPrevious(vec!["a".to_owned()], vec!["b".to_owned()], None);
+        assert_eq!(action, expected);
+    }
+
+    #[test]
+    fn decodes_go_to_if_previous_with_default() {
+        let action = action(b"\x08\x03a\0b\0c\0");
+        let expected = Action::GoToIfPrevious(
+            vec!["a".to_owned()],
+            vec!["b".to_owned()],
+            Some("c".to_owned()),
+        );
+        assert_eq!(action, expected);
     }
 }
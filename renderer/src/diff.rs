@@ -0,0 +1,361 @@
+use std::fmt;
+
+use crate::timeline;
+use crate::types::{Action, Animation, Shape, Sprite};
+
+/// A semantic comparison between two versions of an animation.
+#[derive(Debug, Clone, Default)]
+pub struct AnimationDiff {
+    /// Changes in the order they were found, sorted by kind and id.
+    pub changes: Vec<Change>,
+}
+
+impl AnimationDiff {
+    /// Compares two animations, matching sprites and shapes by their IDs.
+    pub fn compute(old: &Animation, new: &Animation) -> AnimationDiff {
+        let mut changes = vec![];
+
+        if old.version.0 != new.version.0 {
+            changes.push(Change::VersionChanged {
+                old: old.version.0,
+                new: new.version.0,
+            });
+        }
+        if old.header != new.header {
+            changes.push(Change::HeaderChanged {
+                old: old.header,
+                new: new.header,
+            });
+        }
+        if old.frame_rate != new.frame_rate {
+            changes.push(Change::FrameRateChanged {
+                old: old.frame_rate,
+                new: new.frame_rate,
+            });
+        }
+        let old_texture = old.texture.as_ref().map(|tex| tex.name.clone());
+        let new_texture = new.texture.as_ref().map(|tex| tex.name.clone());
+        if old_texture != new_texture {
+            changes.push(Change::TextureChanged {
+                old: old_texture,
+                new: new_texture,
+            });
+        }
+
+        let mut shape_ids = old
+            .shapes
+            .keys()
+            .chain(new.shapes.keys())
+            .collect::<Vec<_>>();
+        shape_ids.sort_unstable();
+        shape_ids.dedup();
+        for id in shape_ids {
+            match (old.shapes.get(id), new.shapes.get(id)) {
+                (Some(_), None) => changes.push(Change::ShapeRemoved(*id)),
+                (None, Some(_)) => changes.push(Change::ShapeAdded(*id)),
+                (Some(old), Some(new)) if old != new => changes.push(Change::ShapeChanged {
+                    old: old.clone(),
+                    new: new.clone(),
+                }),
+                _ => {}
+            }
+        }
+
+        let mut sprite_ids = old
+            .sprites
+            .keys()
+            .chain(new.sprites.keys())
+            .collect::<Vec<_>>();
+        sprite_ids.sort_unstable();
+        sprite_ids.dedup();
+        for id in sprite_ids {
+            match (old.sprites.get(id), new.sprites.get(id)) {
+                (Some(sprite), None) => changes.push(Change::SpriteRemoved(SpriteRef::new(sprite))),
+                (None, Some(sprite)) => changes.push(Change::SpriteAdded(SpriteRef::new(sprite))),
+                (Some(old_sprite), Some(new_sprite)) => {
+                    diff_sprite(old, old_sprite, new, new_sprite, &mut changes);
+                }
+                (None, None) => {}
+            }
+        }
+
+        AnimationDiff { changes }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl fmt::Display for AnimationDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+fn diff_sprite(
+    old_anm: &Animation,
+    old: &Sprite,
+    new_anm: &Animation,
+    new: &Sprite,
+    changes: &mut Vec<Change>,
+) {
+    let sprite = SpriteRef::new(new);
+    if old.name.name != new.name.name {
+        changes.push(Change::SpriteRenamed {
+            id: new.id,
+            old: old.name.name.clone(),
+            new: new.name.name.clone(),
+        });
+    }
+    if old.frame_count() != new.frame_count() {
+        changes.push(Change::FrameCountChanged {
+            sprite: sprite.clone(),
+            old: old.frame_count(),
+            new: new.frame_count(),
+        });
+    }
+
+    let frames = old.frame_count().min(new.frame_count()) as u32;
+    let keyframes = (0..frames)
+        .filter(|&frame| {
+            timeline::placements(old_anm, old, frame) != timeline::placements(new_anm, new, frame)
+        })
+        .collect::<Vec<_>>();
+    if !keyframes.is_empty() {
+        changes.push(Change::KeyframesChanged {
+            sprite: sprite.clone(),
+            frames: keyframes,
+        });
+    }
+
+    for frame in 0..frames {
+        let old_actions = timeline::actions(old_anm, old, frame);
+        let new_actions = timeline::actions(new_anm, new, frame);
+        if old_actions != new_actions {
+            changes.push(Change::ActionsChanged {
+                sprite: sprite.clone(),
+                frame,
+                old: old_actions.into_iter().cloned().collect(),
+                new: new_actions.into_iter().cloned().collect(),
+            });
+        }
+    }
+}
+
+/// A single difference between two animations.
+#[derive(Debug, Clone)]
+pub enum Change {
+    VersionChanged {
+        old: u8,
+        new: u8,
+    },
+    HeaderChanged {
+        old: i16,
+        new: i16,
+    },
+    FrameRateChanged {
+        old: u8,
+        new: u8,
+    },
+    TextureChanged {
+        old: Option<String>,
+        new: Option<String>,
+    },
+    ShapeAdded(i16),
+    ShapeRemoved(i16),
+    /// The UV rectangle, size or offset of a shape changed.
+    ShapeChanged {
+        old: Shape,
+        new: Shape,
+    },
+    SpriteAdded(SpriteRef),
+    SpriteRemoved(SpriteRef),
+    SpriteRenamed {
+        id: i16,
+        old: Option<String>,
+        new: Option<String>,
+    },
+    FrameCountChanged {
+        sprite: SpriteRef,
+        old: usize,
+        new: usize,
+    },
+    /// The children or their transforms differ on the listed frames.
+    KeyframesChanged {
+        sprite: SpriteRef,
+        frames: Vec<u32>,
+    },
+    ActionsChanged {
+        sprite: SpriteRef,
+        frame: u32,
+        old: Vec<Action>,
+        new: Vec<Action>,
+    },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::VersionChanged { old, new } => {
+                write!(f, "~ version: {:#04x} -> {:#04x}", old, new)
+            }
+            Change::HeaderChanged { old, new } => write!(f, "~ header: {} -> {}", old, new),
+            Change::FrameRateChanged { old, new } => write!(f, "~ frame rate: {} -> {}", old, new),
+            Change::TextureChanged { old, new } => {
+                write!(f, "~ texture: {:?} -> {:?}", old, new)
+            }
+            Change::ShapeAdded(id) => write!(f, "+ shape #{}", id),
+            Change::ShapeRemoved(id) => write!(f, "- shape #{}", id),
+            Change::ShapeChanged { old, new } => {
+                write!(f, "~ shape #{}:", new.id)?;
+                let old_uv = [old.top, old.left, old.bottom, old.right];
+                let new_uv = [new.top, new.left, new.bottom, new.right];
+                if old_uv != new_uv {
+                    write!(f, " uv {:?} -> {:?}", old_uv, new_uv)?;
+                }
+                if (old.width, old.height) != (new.width, new.height) {
+                    write!(
+                        f,
+                        " size {}x{} -> {}x{}",
+                        old.width, old.height, new.width, new.height
+                    )?;
+                }
+                if (old.offset_x, old.offset_y) != (new.offset_x, new.offset_y) {
+                    write!(
+                        f,
+                        " offset ({}, {}) -> ({}, {})",
+                        old.offset_x, old.offset_y, new.offset_x, new.offset_y
+                    )?;
+                }
+                if old.texture_index != new.texture_index {
+                    write!(f, " texture {} -> {}", old.texture_index, new.texture_index)?;
+                }
+                Ok(())
+            }
+            Change::SpriteAdded(sprite) => write!(f, "+ sprite {}", sprite),
+            Change::SpriteRemoved(sprite) => write!(f, "- sprite {}", sprite),
+            Change::SpriteRenamed { id, old, new } => {
+                write!(f, "~ sprite #{}: renamed {:?} -> {:?}", id, old, new)
+            }
+            Change::FrameCountChanged { sprite, old, new } => {
+                write!(f, "~ sprite {}: frame count {} -> {}", sprite, old, new)
+            }
+            Change::KeyframesChanged { sprite, frames } => {
+                write!(
+                    f,
+                    "~ sprite {}: transforms changed on frames {:?}",
+                    sprite, frames
+                )
+            }
+            Change::ActionsChanged {
+                sprite,
+                frame,
+                old,
+                new,
+            } => write!(
+                f,
+                "~ sprite {}: actions on frame {}: {:?} -> {:?}",
+                sprite, frame, old, new
+            ),
+        }
+    }
+}
+
+/// Identifies a sprite in a diff by its ID and name.
+#[derive(Debug, Clone)]
+pub struct SpriteRef {
+    pub id: i16,
+    pub name: Option<String>,
+}
+
+impl SpriteRef {
+    #[inline]
+    fn new(sprite: &Sprite) -> SpriteRef {
+        SpriteRef {
+            id: sprite.id,
+            name: sprite.name.name.clone(),
+        }
+    }
+}
+
+impl fmt::Display for SpriteRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "#{} ({})", self.id, name),
+            None => write!(f, "#{}", self.id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::types::SpritePayload;
+
+    #[test]
+    fn finds_no_changes_between_equal_animations() {
+        let animation = fixtures::animation();
+        assert!(AnimationDiff::compute(&animation, &animation).is_empty());
+    }
+
+    #[test]
+    fn reports_header_and_sprite_changes() {
+        let old = fixtures::animation();
+        let mut new = fixtures::animation();
+        new.frame_rate = 24;
+        new.shapes.remove(&2);
+        new.sprites.get_mut(&10).unwrap().name.name = Some("1_Renamed".to_owned());
+
+        let changes = AnimationDiff::compute(&old, &new).changes;
+        assert!(matches!(
+            changes[..],
+            [
+                Change::FrameRateChanged { old: 30, new: 24 },
+                Change::ShapeRemoved(2),
+                Change::SpriteRenamed { id: 10, .. },
+            ]
+        ));
+    }
+
+    #[test]
+    fn lists_frames_with_changed_transforms() {
+        let old = fixtures::animation();
+        let mut new = fixtures::animation();
+        // the third translation is only used on the second frame of the root sprite
+        new.transform.as_mut().unwrap().translations[4] = 30.;
+
+        let changes = AnimationDiff::compute(&old, &new).changes;
+        let frames = changes
+            .iter()
+            .filter_map(|change| match change {
+                Change::KeyframesChanged { sprite, frames } => Some((sprite.id, frames.clone())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(frames, vec![(fixtures::ROOT, vec![1])]);
+    }
+
+    #[test]
+    fn reports_actions_per_frame() {
+        let old = fixtures::animation();
+        let mut new = fixtures::animation();
+        let root = new.sprites.get_mut(&fixtures::ROOT).unwrap();
+        if let SpritePayload::Indexed(_, _, action_info) = &mut root.payload {
+            action_info[5] = 1;
+        }
+
+        let changes = AnimationDiff::compute(&old, &new).changes;
+        assert!(matches!(
+            &changes[..],
+            [Change::ActionsChanged { frame: 2, old, new, .. }]
+                if old == &[Action::GoTo("1_AnimStatique".to_owned(), Some(50))]
+                    && new == &[Action::Hit]
+        ));
+    }
+}
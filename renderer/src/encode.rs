@@ -0,0 +1,563 @@
+use std::io;
+
+use byteorder::*;
+use hashbrown::{HashMap, HashSet};
+
+use crate::types::*;
+
+pub trait Encode {
+    fn encode<W: io::Write>(&self, cursor: &mut W) -> io::Result<()>;
+}
+
+pub trait EncodeExt: io::Write + Sized {
+    #[inline]
+    fn encode<A: Encode + ?Sized>(&mut self, value: &A) -> io::Result<()> {
+        value.encode(self)
+    }
+
+    fn encode_prefixed<P, A>(&mut self, items: &[A]) -> io::Result<()>
+    where
+        P: Encode + TryFrom<usize>,
+        A: Encode,
+    {
+        let count = P::try_from(items.len()).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Too many elements: {}", items.len()),
+            )
+        })?;
+        self.encode(&count)?;
+        self.encode_all(items)
+    }
+
+    fn encode_all<A: Encode>(&mut self, items: &[A]) -> io::Result<()> {
+        for item in items {
+            self.encode(item)?;
+        }
+        Ok(())
+    }
+
+    fn encode_opt<A: Encode>(&mut self, present: bool, value: Option<&A>) -> io::Result<()> {
+        match (present, value) {
+            (true, Some(value)) => self.encode(value),
+            (false, None) => Ok(()),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Optional value does not match its flag",
+            )),
+        }
+    }
+}
+
+impl<W: io::Write> EncodeExt for W {}
+
+impl<A: Encode + ?Sized> Encode for &A {
+    #[inline]
+    fn encode<W: io::Write>(&self, cursor: &mut W) -> io::Result<()> {
+        (**self).encode(cursor)
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    #[inline]
+    fn encode<W: io::Write>(&self, cursor: &mut W) -> io::Result<()> {
+        cursor.encode(&self.0)?;
+        cursor.encode(&self.1)
+    }
+}
+
+impl Encode for i8 {
+    #[inline]
+    fn encode<W: io::Write>(&self, cursor: &mut W) -> io::Result<()> {
+        cursor.write_i8(*self)
+    }
+}
+
+impl Encode for u8 {
+    #[inline]
+    fn encode<W: io::Write>(&self, cursor: &mut W) -> io::Result<()> {
+        cursor.write_u8(*self)
+    }
+}
+
+impl Encode for i16 {
+    #[inline]
+    fn encode<W: io::Write>(&self, cursor: &mut W) -> io::Result<()> {
+        cursor.write_i16::<LittleEndian>(*self)
+    }
+}
+
+impl Encode for u16 {
+    #[inline]
+    fn encode<W: io::Write>(&self, cursor: &mut W) -> io::Result<()> {
+        cursor.write_u16::<LittleEndian>(*self)
+    }
+}
+
+impl Encode for i32 {
+    #[inline]
+    fn encode<W: io::Write>(&self, cursor: &mut W) -> io::Result<()> {
+        cursor.write_i32::<LittleEndian>(*self)
+    }
+}
+
+impl Encode for u32 {
+    #[inline]
+    fn encode<W: io::Write>(&self, cursor: &mut W) -> io::Result<()> {
+        cursor.write_u32::<LittleEndian>(*self)
+    }
+}
+
+impl Encode for f32 {
+    #[inline]
+    fn encode<W: io::Write>(&self, cursor: &mut W) -> io::Result<()> {
+        cursor.write_f32::<LittleEndian>(*self)
+    }
+}
+
+impl Encode for f64 {
+    #[inline]
+    fn encode<W: io::Write>(&self, cursor: &mut W) -> io::Result<()> {
+        cursor.write_f64::<LittleEndian>(*self)
+    }
+}
+
+impl Encode for String {
+    #[inline]
+    fn encode<W: io::Write>(&self, cursor: &mut W) -> io::Result<()> {
+        self.as_str().encode(cursor)
+    }
+}
+
+impl Encode for str {
+    fn encode<W: io::Write>(&self, cursor: &mut W) -> io::Result<()> {
+        if self.as_bytes().contains(&0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("String contains a null byte: {:?}", self),
+            ));
+        }
+        cursor.write_all(self.as_bytes())?;
+        cursor.write_u8(0)
+    }
+}
+
+impl Encode for Animation {
+    fn encode<W: io::Write>(&self, cursor: &mut W) -> io::Result<()> {
+        cursor.encode(&self.version)?;
+        cursor.encode(&self.header)?;
+        cursor.encode(&self.frame_rate)?;
+        cursor.encode_opt(self.version.use_local_index(), self.index.as_ref())?;
+        cursor.encode(&u16::from(self.texture.is_some()))?;
+        if let Some(texture) = &self.texture {
+            cursor.encode(texture)?;
+        }
+        cursor.encode_prefixed::<u16, _>(&stored_order(&self.shapes, &self.shape_order))?;
+        cursor.encode_opt(self.version.use_transform_index(), self.transform.as_ref())?;
+        cursor.encode_prefixed::<u16, _>(&stored_order(&self.sprites, &self.sprite_order))?;
+        cursor.encode_prefixed::<u16, _>(&self.imports)
+    }
+}
+
+/// Lists the values of a map in the given order of IDs, followed by the values missing from it
+/// sorted by ID.
+fn stored_order<'a, T>(map: &'a HashMap<i16, T>, order: &[i16]) -> Vec<&'a T> {
+    let mut seen = HashSet::with_capacity(map.len());
+    let mut values = order
+        .iter()
+        .filter(|id| seen.insert(**id))
+        .filter_map(|id| map.get(id))
+        .collect::<Vec<_>>();
+    let mut rest = map
+        .iter()
+        .filter(|(id, _)| !seen.contains(*id))
+        .collect::<Vec<_>>();
+    rest.sort_unstable_by_key(|(id, _)| **id);
+    values.extend(rest.into_iter().map(|(_, value)| value));
+    values
+}
+
+impl Encode for AnimationVersion {
+    #[inline]
+    fn encode<W: io::Write>(&self, cursor: &mut W) -> io::Result<()> {
+        cursor.encode(&self.0)
+    }
+}
+
+impl Encode for Texture {
+    fn encode<W: io::Write>(&self, cursor: &mut W) -> io::Result<()> {
+        cursor.encode(&self.name)?;
+        cursor.encode(&self.crc)
+    }
+}
+
+impl Encode for Shape {
+    fn encode<W: io::Write>(&self, cursor: &mut W) -> io::Result<()> {
+        let uv = |value: f32| (value * 65535f32).round() as u16;
+        cursor.encode(&self.id)?;
+        cursor.encode(&self.texture_index)?;
+        cursor.encode(&uv(self.top))?;
+        cursor.encode(&uv(self.left))?;
+        cursor.encode(&uv(self.bottom))?;
+        cursor.encode(&uv(self.right))?;
+        cursor.encode(&self.width)?;
+        cursor.encode(&self.height)?;
+        cursor.encode(&self.offset_x)?;
+        cursor.encode(&self.offset_y)
+    }
+}
+
+impl Encode for TransformTable {
+    fn encode<W: io::Write>(&self, cursor: &mut W) -> io::Result<()> {
+        cursor.encode_prefixed::<u32, _>(&self.colors)?;
+        cursor.encode_prefixed::<u32, _>(&self.rotations)?;
+        cursor.encode_prefixed::<u32, _>(&self.translations)?;
+        cursor.encode_prefixed::<u32, _>(&self.actions)
+    }
+}
+
+impl Encode for Sprite {
+    fn encode<W: io::Write>(&self, cursor: &mut W) -> io::Result<()> {
+        let tag: i8 = match &self.payload {
+            SpritePayload::Single(_, _) => 1,
+            SpritePayload::SingleNoAction(_) => 2,
+            SpritePayload::SingleFrame(_, _) => 3,
+            SpritePayload::Indexed(_, _, _) => 4,
+        };
+        cursor.encode(&tag)?;
+        cursor.encode(&self.id)?;
+        cursor.encode(&self.flags)?;
+        cursor.encode_opt(self.flags.has_name(), self.name.name.as_ref())?;
+        cursor.encode(&self.name.name_crc)?;
+        cursor.encode(&self.name.base_name_crc)?;
+        match &self.payload {
+            SpritePayload::Single(sprite_id, action_info) => {
+                cursor.encode(sprite_id)?;
+                cursor.encode_prefixed::<u16, _>(action_info)?;
+            }
+            SpritePayload::SingleNoAction(sprite_id) => cursor.encode(sprite_id)?,
+            SpritePayload::SingleFrame(sprite_ids, action_info) => {
+                cursor.encode_prefixed::<u16, _>(sprite_ids)?;
+                cursor.encode_prefixed::<u16, _>(action_info)?;
+            }
+            SpritePayload::Indexed(frame_pos, sprite_ids, action_info) => {
+                cursor.encode_prefixed::<u16, _>(frame_pos)?;
+                cursor.encode_prefixed::<u16, _>(sprite_ids)?;
+                cursor.encode_prefixed::<u16, _>(action_info)?;
+            }
+        }
+        cursor.encode(&self.frame_data)
+    }
+}
+
+impl Encode for SpriteFlags {
+    #[inline]
+    fn encode<W: io::Write>(&self, cursor: &mut W) -> io::Result<()> {
+        cursor.encode(&self.0)
+    }
+}
+
+impl Encode for FrameData {
+    fn encode<W: io::Write>(&self, cursor: &mut W) -> io::Result<()> {
+        match self {
+            FrameData::Bytes(vec) => {
+                cursor.encode(&1u8)?;
+                cursor.encode_prefixed::<u32, _>(vec)
+            }
+            FrameData::Shorts(vec) => {
+                cursor.encode(&2u8)?;
+                cursor.encode_prefixed::<u32, _>(vec)
+            }
+            FrameData::Ints(vec) => {
+                cursor.encode(&4u8)?;
+                cursor.encode_prefixed::<u32, _>(vec)
+            }
+        }
+    }
+}
+
+impl Encode for AnimationIndex {
+    fn encode<W: io::Write>(&self, cursor: &mut W) -> io::Result<()> {
+        let flags = &self.flags;
+        cursor.encode(flags)?;
+        cursor.encode_opt(flags.has_scale(), self.scale.as_ref())?;
+        cursor.encode_opt(flags.has_render_radius(), self.render_radius.as_ref())?;
+        if flags.has_extension() != self.file_names.is_some()
+            || flags.has_hiding_part() != self.parts_hidden_by.is_some()
+            || flags.can_hide_part() != self.parts_to_be_hidden.is_some()
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Animation index does not match its flags",
+            ));
+        }
+        if let Some(file_names) = &self.file_names {
+            cursor.encode_prefixed::<u16, _>(file_names)?;
+        }
+        if let Some(parts) = &self.parts_hidden_by {
+            cursor.encode_prefixed::<u8, _>(parts)?;
+        }
+        if let Some(parts) = &self.parts_to_be_hidden {
+            cursor.encode_prefixed::<u8, _>(parts)?;
+        }
+        cursor.encode_opt(flags.is_extended(), self.extension.as_ref())?;
+        cursor.encode_prefixed::<u16, _>(&self.animation_files)
+    }
+}
+
+impl Encode for AnimationFlags {
+    #[inline]
+    fn encode<W: io::Write>(&self, cursor: &mut W) -> io::Result<()> {
+        cursor.encode(&self.0)
+    }
+}
+
+impl Encode for HideablePart {
+    fn encode<W: io::Write>(&self, cursor: &mut W) -> io::Result<()> {
+        cursor.encode(&self.crc_key)?;
+        cursor.encode(&self.crc_to_hide)
+    }
+}
+
+impl Encode for HiddenPart {
+    fn encode<W: io::Write>(&self, cursor: &mut W) -> io::Result<()> {
+        cursor.encode(&self.item_name)?;
+        cursor.encode(&self.crc_key)
+    }
+}
+
+impl Encode for AnimationExtension {
+    fn encode<W: io::Write>(&self, cursor: &mut W) -> io::Result<()> {
+        let mut flags = self.flags & !0x3;
+        if self.heights.is_some() {
+            flags |= 0x1;
+        }
+        if self.highlight_color.is_some() {
+            flags |= 0x2;
+        }
+        cursor.encode(&flags)?;
+        if let Some(heights) = &self.heights {
+            let mut entries = heights.iter().collect::<Vec<_>>();
+            entries.sort_by_key(|(key, _)| **key);
+            cursor.encode_prefixed::<u16, _>(
+                &entries
+                    .into_iter()
+                    .map(|(key, height)| (*key, height.wrapping_sub(1)))
+                    .collect::<Vec<_>>(),
+            )?;
+        }
+        cursor.encode_opt(flags & 0x2 == 0x2, self.highlight_color.as_ref())
+    }
+}
+
+impl Encode for Color {
+    fn encode<W: io::Write>(&self, cursor: &mut W) -> io::Result<()> {
+        cursor.encode(&self.red)?;
+        cursor.encode(&self.green)?;
+        cursor.encode(&self.blue)
+    }
+}
+
+impl Encode for AnimationFile {
+    fn encode<W: io::Write>(&self, cursor: &mut W) -> io::Result<()> {
+        cursor.encode(&self.name)?;
+        cursor.encode(&self.crc)?;
+        cursor.encode(&self.file_index)
+    }
+}
+
+impl Encode for Action {
+    fn encode<W: io::Write>(&self, cursor: &mut W) -> io::Result<()> {
+        let param_count = |count: usize| {
+            u8::try_from(count).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Too many action parameters: {}", count),
+                )
+            })
+        };
+        match self {
+            Action::GoTo(name, percent) => {
+                cursor.encode(&1u8)?;
+                cursor.encode(&param_count(1 + usize::from(percent.is_some()))?)?;
+                cursor.encode(name)?;
+                if let Some(percent) = percent {
+                    cursor.encode(percent)?;
+                }
+            }
+            Action::GoToStatic => {
+                cursor.encode(&2u8)?;
+                cursor.encode(&0u8)?;
+            }
+            Action::RunScript(script) => {
+                cursor.encode(&3u8)?;
+                cursor.encode(&1u8)?;
+                cursor.encode(script)?;
+            }
+            Action::GoToRandom(names, percents) => {
+                cursor.encode(&4u8)?;
+                if percents.is_empty() {
+                    cursor.encode(&param_count(names.len())?)?;
+                    cursor.encode_all(names)?;
+                } else {
+                    if names.len() != percents.len() + 1 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!(
+                                "{} names for {} percents in a random go to",
+                                names.len(),
+                                percents.len()
+                            ),
+                        ));
+                    }
+                    cursor.encode(&param_count(1 + percents.len() * 2)?)?;
+                    cursor.encode_all(names)?;
+                    cursor.encode_all(percents)?;
+                }
+            }
+            Action::Hit => {
+                cursor.encode(&5u8)?;
+                cursor.encode(&0u8)?;
+            }
+            Action::Delete => {
+                cursor.encode(&6u8)?;
+                cursor.encode(&0u8)?;
+            }
+            Action::End => {
+                cursor.encode(&7u8)?;
+                cursor.encode(&0u8)?;
+            }
+            Action::GoToIfPrevious(previous, next, default) => {
+                cursor.encode(&8u8)?;
+                if previous.len() != next.len() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "{} previous names for {} next names in a conditional go to",
+                            previous.len(),
+                            next.len()
+                        ),
+                    ));
+                }
+                let count = previous.len();
+                cursor.encode(&param_count(count * 2 + usize::from(default.is_some()))?)?;
+                for (previous, next) in previous.iter().zip(next) {
+                    cursor.encode(previous)?;
+                    cursor.encode(next)?;
+                }
+                if let Some(default) = default {
+                    cursor.encode(default)?;
+                }
+            }
+            Action::AddParticle(particle_id, offset_x, offset_y, offset_z) => {
+                let offsets = [offset_x, offset_y, offset_z]
+                    .into_iter()
+                    .map_while(|offset| *offset)
+                    .collect::<Vec<_>>();
+                cursor.encode(&9u8)?;
+                cursor.encode(&param_count(1 + offsets.len())?)?;
+                cursor.encode(particle_id)?;
+                cursor.encode_all(&offsets)?;
+            }
+            Action::SetRadius(radius) => {
+                cursor.encode(&10u8)?;
+                cursor.encode(&1u8)?;
+                cursor.encode(radius)?;
+            }
+            Action::Unknown { id, raw_params } => {
+                cursor.encode(id)?;
+                cursor.encode(&param_count(raw_params.len())?)?;
+                for param in raw_params {
+                    cursor.write_all(param)?;
+                    cursor.encode(&0u8)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Encode for Import {
+    fn encode<W: io::Write>(&self, cursor: &mut W) -> io::Result<()> {
+        cursor.encode(&self.id)?;
+        cursor.encode(&self.name)?;
+        cursor.encode(&self.crc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::Decode;
+    use crate::fixtures;
+
+    fn encode(animation: &Animation) -> Vec<u8> {
+        let mut bytes = vec![];
+        animation.encode(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trips_through_decoding() {
+        let bytes = encode(&fixtures::animation());
+        let decoded = Animation::decode(&mut bytes.as_slice()).unwrap();
+        assert_eq!(encode(&decoded), bytes);
+    }
+
+    #[test]
+    fn keeps_unknown_extension_flags() {
+        let bytes = encode(&fixtures::animation());
+        let decoded = Animation::decode(&mut bytes.as_slice()).unwrap();
+        let extension = decoded.index.and_then(|index| index.extension).unwrap();
+        assert_eq!(extension.flags, 0x107);
+    }
+
+    #[test]
+    fn rejects_values_not_matching_their_flags() {
+        let mut animation = fixtures::animation();
+        animation.version = AnimationVersion(0x19);
+        let err = animation.encode(&mut vec![]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn keeps_the_decoded_order() {
+        let mut animation = fixtures::animation();
+        animation.shape_order = vec![2, 1];
+        animation.sprite_order = vec![fixtures::WALK, 12, 10, fixtures::ROOT, 11];
+        let bytes = encode(&animation);
+        let decoded = Animation::decode(&mut bytes.as_slice()).unwrap();
+        assert_eq!(decoded.shape_order, [2, 1]);
+        assert_eq!(decoded.sprite_order, animation.sprite_order);
+        assert_eq!(encode(&decoded), bytes);
+    }
+
+    #[test]
+    fn encodes_shapes_and_sprites_missing_from_the_order_by_id() {
+        let mut animation = fixtures::animation();
+        animation.sprite_order = vec![fixtures::ROOT, 99, fixtures::ROOT];
+        let bytes = encode(&animation);
+        let decoded = Animation::decode(&mut bytes.as_slice()).unwrap();
+        assert_eq!(decoded.shape_order, [1, 2]);
+        assert_eq!(
+            decoded.sprite_order,
+            [fixtures::ROOT, 10, 11, 12, fixtures::WALK]
+        );
+    }
+
+    #[test]
+    fn rejects_mismatched_action_names() {
+        let actions = [
+            Action::GoToRandom(vec!["a".to_owned(), "b".to_owned()], vec![30, 70]),
+            Action::GoToIfPrevious(
+                vec!["a".to_owned(), "b".to_owned()],
+                vec!["c".to_owned()],
+                None,
+            ),
+        ];
+        for action in actions {
+            let err = action.encode(&mut vec![]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{:?}", action);
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! A small animation exercising every sprite payload, action and optional block, shared by the
+//! unit tests.
+use hashbrown::HashMap;
+
+use crate::types::*;
+
+pub const ROOT: i16 = 20;
+pub const WALK: i16 = 21;
+
+/// Builds the test animation.
+///
+/// The root sprite plays three frames: the first two place sprites 10 and 11, the last one
+/// places sprite 12. Sprite 21 is a two frame walk cycle placing shape 1.
+pub fn animation() -> Animation {
+    let shapes = [1, 2]
+        .into_iter()
+        .map(|id| {
+            let shape = Shape {
+                id,
+                texture_index: 0,
+                top: 0.,
+                left: 0.25 * f32::from(id - 1),
+                bottom: 0.5,
+                right: 0.25 * f32::from(id),
+                width: 16,
+                height: 32,
+                offset_x: -8.,
+                offset_y: -32.,
+            };
+            (id, shape)
+        })
+        .collect();
+
+    let sprites = [
+        sprite(
+            10,
+            Some("1_Part"),
+            SpritePayload::Single(1, vec![1]),
+            vec![2, 2],
+        ),
+        sprite(11, None, SpritePayload::SingleNoAction(2), vec![0]),
+        sprite(
+            12,
+            None,
+            SpritePayload::SingleFrame(vec![1, 2], vec![]),
+            vec![4, 0],
+        ),
+        sprite(
+            ROOT,
+            Some("1_AnimStatique"),
+            SpritePayload::Indexed(
+                vec![0, 0, 0, 4, 0, 1, 9, 3, 4],
+                vec![2, 10, 11, 1, 12],
+                vec![0, 2, 0, 3, 1, 2],
+            ),
+            vec![2, 0, 2, 2, 2, 4, 3, 0, 4, 0],
+        ),
+        sprite(
+            WALK,
+            Some("1_AnimMarche"),
+            SpritePayload::Indexed(vec![0, 0, 2, 0], vec![1, 1], vec![]),
+            vec![2, 0, 2, 2],
+        ),
+    ]
+    .into_iter()
+    .map(|sprite| (sprite.id, sprite))
+    .collect();
+
+    Animation {
+        version: AnimationVersion(0x1B),
+        header: 0,
+        frame_rate: 30,
+        index: Some(AnimationIndex {
+            flags: AnimationFlags(0xCF),
+            scale: Some(1.5),
+            render_radius: Some(20.),
+            file_names: Some(vec!["7001".to_owned()]),
+            animation_files: vec![AnimationFile {
+                name: "1_AnimStatique".to_owned(),
+                crc: 7,
+                file_index: 0,
+            }],
+            parts_to_be_hidden: Some(vec![HiddenPart {
+                item_name: "Hat".to_owned(),
+                crc_key: 3,
+            }]),
+            parts_hidden_by: Some(vec![HideablePart {
+                crc_key: 3,
+                crc_to_hide: 4,
+            }]),
+            extension: Some(AnimationExtension {
+                flags: 0x107,
+                heights: Some(HashMap::from([(7, 3), (8, -2)])),
+                highlight_color: Some(Color::new(1., 0.5, 0.25, 1.)),
+            }),
+        }),
+        texture: Some(Texture {
+            name: "7001".to_owned(),
+            crc: 42,
+        }),
+        shapes,
+        transform: Some(TransformTable {
+            colors: vec![1., 1., 1., 0.5],
+            rotations: vec![0., 1., -1., 0.],
+            translations: vec![0., 0., 10., -5., 20., -10.],
+            actions: vec![
+                Action::GoTo("1_AnimMarche".to_owned(), None),
+                Action::Hit,
+                Action::GoTo("1_AnimStatique".to_owned(), Some(50)),
+                Action::GoToStatic,
+                Action::RunScript("script".to_owned()),
+                Action::GoToRandom(
+                    vec!["#optimized".to_owned(), "a".to_owned(), "b".to_owned()],
+                    vec![30, 70],
+                ),
+                Action::GoToRandom(vec!["a".to_owned(), "b".to_owned()], vec![]),
+                Action::Delete,
+                Action::End,
+                Action::GoToIfPrevious(vec!["a".to_owned()], vec!["b".to_owned()], None),
+                Action::GoToIfPrevious(
+                    vec!["a".to_owned()],
+                    vec!["b".to_owned()],
+                    Some("c".to_owned()),
+                ),
+                Action::AddParticle(1, Some(2), Some(-3), None),
+                Action::SetRadius(4),
+            ],
+        }),
+        sprites,
+        imports: vec![Import {
+            id: 30,
+            name: "shared".to_owned(),
+            crc: 9,
+        }],
+        shape_order: vec![],
+        sprite_order: vec![],
+    }
+}
+
+fn sprite(id: i16, name: Option<&str>, payload: SpritePayload, frame_data: Vec<u8>) -> Sprite {
+    Sprite {
+        id,
+        name: SpriteName {
+            name: name.map(str::to_owned),
+            name_crc: i32::from(id) * 100,
+            base_name_crc: i32::from(id) * 1000,
+        },
+        flags: SpriteFlags(if name.is_some() { 0x40 } else { 0 }),
+        frame_data: FrameData::Bytes(frame_data),
+        payload,
+    }
+}
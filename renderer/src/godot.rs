@@ -0,0 +1,410 @@
+//! Export of animations to Godot 4 resources.
+//!
+//! [`sprite_frames`] writes a `SpriteFrames` resource (`.tres`) referencing pre-rendered frame
+//! images, while [`scene`] writes a scene (`.tscn`) that rebuilds the sprite hierarchy from
+//! `Node2D` and `Sprite2D` nodes, animated by an `AnimationPlayer` with one animation per
+//! named sprite. Instances are named after their path, e.g. `n12_0/n7_1` for the second
+//! occurrence of `7` inside the first `12`. Animations are keyed by sprite name, see
+//! [`animation_names`].
+use std::fmt::Write;
+
+use euclid::default::Transform2D;
+
+use crate::timeline::{self, Instance};
+use crate::types::Animation;
+
+/// Rendered frames of a named sprite.
+#[derive(Debug, Clone)]
+pub struct FrameSequence {
+    /// ID of the sprite the frames were rendered from.
+    pub sprite: i16,
+    pub name: String,
+    /// Resource paths of the frame images, e.g. `res://frames/walk_0.png`.
+    pub frames: Vec<String>,
+}
+
+/// Builds a `SpriteFrames` resource playing every sequence at the animation frame rate.
+pub fn sprite_frames(animation: &Animation, sequences: &[FrameSequence]) -> String {
+    let resource_count = sequences.iter().map(|seq| seq.frames.len()).sum::<usize>();
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "[gd_resource type=\"SpriteFrames\" load_steps={} format=3]\n",
+        resource_count + 1
+    );
+
+    let mut resource_id = 0;
+    for seq in sequences {
+        for path in &seq.frames {
+            resource_id += 1;
+            let _ = writeln!(
+                out,
+                "[ext_resource type=\"Texture2D\" path=\"{}\" id=\"{}\"]",
+                path, resource_id
+            );
+        }
+    }
+
+    let _ = writeln!(out, "\n[resource]\nanimations = [");
+    let names = animation_names(
+        &sequences
+            .iter()
+            .map(|seq| (seq.sprite, seq.name.as_str()))
+            .collect::<Vec<_>>(),
+    );
+    let mut resource_id = 0;
+    for (i, (seq, name)) in sequences.iter().zip(&names).enumerate() {
+        let frames = seq
+            .frames
+            .iter()
+            .map(|_| {
+                resource_id += 1;
+                format!(
+                    "{{\n\"duration\": 1.0,\n\"texture\": ExtResource(\"{}\")\n}}",
+                    resource_id
+                )
+            })
+            .collect::<Vec<_>>();
+        let _ = write!(
+            out,
+            "{{\n\"frames\": [{}],\n\"loop\": true,\n\"name\": &\"{}\",\n\"speed\": {:.1}\n}}",
+            frames.join(", "),
+            escape(name),
+            f32::from(animation.frame_rate)
+        );
+        out.push_str(if i + 1 < sequences.len() { ", " } else { "" });
+    }
+    out.push_str("]\n");
+    out
+}
+
+/// Builds a scene reproducing the sprite hierarchy with keyframed transforms.
+///
+/// The atlas is expected at `atlas_path` and its size is needed to convert shape UVs to
+/// pixel regions.
+pub fn scene(animation: &Animation, atlas_path: &str, width: u32, height: u32) -> String {
+    let mut sprites = animation
+        .sprites
+        .values()
+        .filter_map(|sprite| Some((sprite.name.name.as_deref()?, sprite)))
+        .collect::<Vec<_>>();
+    sprites.sort_unstable_by_key(|(name, sprite)| (*name, sprite.id));
+    let names = animation_names(
+        &sprites
+            .iter()
+            .map(|(name, sprite)| (sprite.id, *name))
+            .collect::<Vec<_>>(),
+    );
+
+    let timelines = sprites
+        .iter()
+        .zip(&names)
+        .map(|((_, sprite), name)| {
+            let frames = (0..sprite.frame_count() as u32)
+                .map(|frame| timeline::instances(animation, sprite, frame))
+                .collect::<Vec<_>>();
+            (name.as_str(), frames)
+        })
+        .collect::<Vec<_>>();
+
+    // every instance seen in any animation becomes a node, parents first
+    let mut nodes: Vec<&Instance> = vec![];
+    for instance in timelines
+        .iter()
+        .flat_map(|(_, frames)| frames.iter().flatten())
+    {
+        if !nodes.iter().any(|node| node.path == instance.path) {
+            nodes.push(instance);
+        }
+    }
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "[gd_scene load_steps={} format=3]\n",
+        timelines.len() + 3
+    );
+    let _ = writeln!(
+        out,
+        "[ext_resource type=\"Texture2D\" path=\"{}\" id=\"atlas\"]\n",
+        atlas_path
+    );
+
+    let frame_time = 1. / f64::from(animation.frame_rate.max(1));
+    for (i, (name, frames)) in timelines.iter().enumerate() {
+        write_animation(&mut out, i, name, frames, &nodes, frame_time);
+    }
+
+    let _ = writeln!(
+        out,
+        "[sub_resource type=\"AnimationLibrary\" id=\"library\"]\n_data = {{"
+    );
+    let entries = timelines
+        .iter()
+        .enumerate()
+        .map(|(i, (name, _))| format!("&\"{}\": SubResource(\"animation_{}\")", escape(name), i))
+        .collect::<Vec<_>>();
+    let _ = writeln!(out, "{}\n}}\n", entries.join(",\n"));
+
+    let scale = animation.scale();
+    let _ = writeln!(out, "[node name=\"Animation\" type=\"Node2D\"]\n");
+    let _ = writeln!(
+        out,
+        "[node name=\"Root\" type=\"Node2D\" parent=\".\"]\nscale = Vector2({}, {})\n",
+        scale, scale
+    );
+
+    for node in &nodes {
+        let parent = match node.parent() {
+            [] => "Root".to_owned(),
+            parent => format!("Root/{}", node_path(parent)),
+        };
+        let name = node_path(&node.path[node.path.len() - 1..]);
+        let _ = writeln!(
+            out,
+            "[node name=\"{}\" type=\"Node2D\" parent=\"{}\"]\n",
+            name, parent
+        );
+
+        let Some(shape) = node.shape.and_then(|id| animation.shapes.get(&id)) else {
+            continue;
+        };
+        let region_w = (shape.right - shape.left) * width as f32;
+        let region_h = (shape.bottom - shape.top) * height as f32;
+        let _ = writeln!(
+            out,
+            "[node name=\"Image\" type=\"Sprite2D\" parent=\"{}/{}\"]",
+            parent, name
+        );
+        let _ = writeln!(
+            out,
+            "position = Vector2({}, {})\nscale = Vector2({}, {})\ntexture = ExtResource(\"atlas\")\n\
+             centered = false\nflip_v = true\nregion_enabled = true\n\
+             region_rect = Rect2({}, {}, {}, {})\n",
+            shape.offset_x,
+            shape.offset_y,
+            f32::from(shape.width) / region_w.max(1.),
+            f32::from(shape.height) / region_h.max(1.),
+            shape.left * width as f32,
+            shape.top * height as f32,
+            region_w,
+            region_h
+        );
+    }
+
+    let _ = writeln!(
+        out,
+        "[node name=\"AnimationPlayer\" type=\"AnimationPlayer\" parent=\".\"]\n\
+         libraries = {{\n&\"\": SubResource(\"library\")\n}}"
+    );
+    out
+}
+
+/// Names the animations of sprites, adding the ID of the sprite to names shared by several
+/// sprites so that every animation gets its own key, e.g. `1_AnimStatique_20`.
+pub fn animation_names(sprites: &[(i16, &str)]) -> Vec<String> {
+    sprites
+        .iter()
+        .map(|&(id, name)| {
+            let shared = sprites.iter().filter(|(_, other)| *other == name).count() > 1;
+            if shared {
+                format!("{}_{}", name, id)
+            } else {
+                name.to_owned()
+            }
+        })
+        .collect()
+}
+
+fn write_animation(
+    out: &mut String,
+    index: usize,
+    name: &str,
+    frames: &[Vec<Instance>],
+    nodes: &[&Instance],
+    frame_time: f64,
+) {
+    let length = frames.len() as f64 * frame_time;
+    let _ = writeln!(
+        out,
+        "[sub_resource type=\"Animation\" id=\"animation_{}\"]\nresource_name = \"{}\"\n\
+         length = {}\nloop_mode = 1\nstep = {}",
+        index,
+        escape(name),
+        length,
+        frame_time
+    );
+
+    let times = (0..frames.len())
+        .map(|frame| (frame as f64 * frame_time).to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let transitions = vec!["1"; frames.len()].join(", ");
+
+    for (track, node) in nodes.iter().enumerate() {
+        let states = frames
+            .iter()
+            .map(|instances| instances.iter().find(|inst| inst.path == node.path))
+            .collect::<Vec<_>>();
+        let transforms = states
+            .iter()
+            .map(|state| match state {
+                Some(inst) => godot_transform(&inst.transform),
+                None => godot_transform(&Transform2D::identity()),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let visibility = states
+            .iter()
+            .map(|state| state.is_some().to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let path = format!("Root/{}", node_path(&node.path));
+        write_track(
+            out,
+            track * 2,
+            &path,
+            "transform",
+            &times,
+            &transitions,
+            &transforms,
+        );
+        write_track(
+            out,
+            track * 2 + 1,
+            &path,
+            "visible",
+            &times,
+            &transitions,
+            &visibility,
+        );
+    }
+    out.push('\n');
+}
+
+fn write_track(
+    out: &mut String,
+    track: usize,
+    path: &str,
+    property: &str,
+    times: &str,
+    transitions: &str,
+    values: &str,
+) {
+    let _ = writeln!(
+        out,
+        "tracks/{0}/type = \"value\"\ntracks/{0}/imported = false\ntracks/{0}/enabled = true\n\
+         tracks/{0}/path = NodePath(\"{1}:{2}\")\ntracks/{0}/interp = 0\n\
+         tracks/{0}/loop_wrap = true\ntracks/{0}/keys = {{\n\
+         \"times\": PackedFloat32Array({3}),\n\"transitions\": PackedFloat32Array({4}),\n\
+         \"update\": 1,\n\"values\": [{5}]\n}}",
+        track, path, property, times, transitions, values
+    );
+}
+
+#[inline]
+fn godot_transform(transform: &Transform2D<f32>) -> String {
+    format!(
+        "Transform2D({}, {}, {}, {}, {}, {})",
+        transform.m11, transform.m12, transform.m21, transform.m22, transform.m31, transform.m32
+    )
+}
+
+fn node_path(path: &[(i16, usize)]) -> String {
+    path.iter()
+        .map(|(id, occurrence)| format!("n{}_{}", id, occurrence))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[inline]
+fn escape(name: &str) -> String {
+    name.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn sequence(sprite: i16, name: &str, frames: usize) -> FrameSequence {
+        FrameSequence {
+            sprite,
+            name: name.to_owned(),
+            frames: (0..frames)
+                .map(|i| format!("res://frames/{}_{}.png", name, i))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn suffixes_only_shared_names() {
+        let names = animation_names(&[(1, "walk"), (2, "idle"), (3, "walk")]);
+        assert_eq!(names, ["walk_1", "idle", "walk_3"]);
+    }
+
+    #[test]
+    fn writes_sprite_frames_with_unique_keys() {
+        let animation = fixtures::animation();
+        let sequences = [
+            sequence(1, "walk", 2),
+            sequence(2, "walk", 1),
+            sequence(3, "idle", 1),
+        ];
+        let tres = sprite_frames(&animation, &sequences);
+
+        assert!(tres.starts_with("[gd_resource type=\"SpriteFrames\" load_steps=5 format=3]"));
+        assert_eq!(tres.matches("[ext_resource ").count(), 4);
+        assert!(tres.contains(
+            "[ext_resource type=\"Texture2D\" path=\"res://frames/walk_0.png\" id=\"3\"]"
+        ));
+        assert!(tres.contains(
+            "\"texture\": ExtResource(\"3\")\n}],\n\"loop\": true,\n\"name\": &\"walk_2\""
+        ));
+        for key in ["&\"walk_1\"", "&\"walk_2\"", "&\"idle\""] {
+            assert_eq!(tres.matches(key).count(), 1, "{}", key);
+        }
+        assert!(tres.contains("\"speed\": 30.0"));
+    }
+
+    #[test]
+    fn writes_a_scene_with_unique_animations() {
+        let mut animation = fixtures::animation();
+        animation
+            .sprites
+            .get_mut(&fixtures::WALK)
+            .unwrap()
+            .name
+            .name = Some("1_AnimStatique".to_owned());
+        let tscn = scene(&animation, "res://atlas.png", 64, 64);
+
+        assert!(tscn.starts_with("[gd_scene load_steps=6 format=3]"));
+        assert!(
+            tscn.contains(
+                "[ext_resource type=\"Texture2D\" path=\"res://atlas.png\" id=\"atlas\"]"
+            )
+        );
+        for (i, key) in ["1_AnimStatique_20", "1_AnimStatique_21", "1_Part"]
+            .into_iter()
+            .enumerate()
+        {
+            assert!(tscn.contains(&format!(
+                "[sub_resource type=\"Animation\" id=\"animation_{}\"]\nresource_name = \"{}\"",
+                i, key
+            )));
+            assert!(tscn.contains(&format!("&\"{}\": SubResource(\"animation_{}\")", key, i)));
+        }
+        assert!(tscn.contains(
+            "[node name=\"Root\" type=\"Node2D\" parent=\".\"]\nscale = Vector2(1.5, 1.5)"
+        ));
+        assert!(tscn.contains("[node name=\"n1_0\" type=\"Node2D\" parent=\"Root/n10_0\"]"));
+        assert!(tscn.contains(
+            "[node name=\"Image\" type=\"Sprite2D\" parent=\"Root/n11_0/n2_0\"]\n\
+             position = Vector2(-8, -32)"
+        ));
+        assert!(tscn.contains("region_rect = Rect2(16, 0, 16, 32)"));
+        assert!(tscn.contains("tracks/14/path = NodePath(\"Root/n1_0:transform\")"));
+        assert!(tscn.contains("\"values\": [Transform2D(1, 0, 0, 1, 10, -5)]"));
+    }
+}
@@ -0,0 +1,113 @@
+//! Highlighting of animations, such as the effect shown in game when an entity is hovered.
+use crate::render::{ColorTransform, Render, SpriteTransform};
+use crate::types::{Animation, Color, Shape, Sprite};
+
+/// Color used for animations that do not define a highlight color.
+pub const DEFAULT_HIGHLIGHT_COLOR: Color = Color::new(1., 0.9, 0.5, 1.);
+
+/// How a highlighted animation is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HighlightMode {
+    /// An outline in the highlight color around the animation.
+    #[default]
+    Outline,
+    /// A translucent layer of the highlight color on top of the animation.
+    Overlay,
+}
+
+/// Describes how to highlight an animation.
+#[derive(Debug, Clone)]
+pub struct Highlight {
+    pub mode: HighlightMode,
+    /// Color used when the animation has no
+    /// [`highlight_color`](crate::types::AnimationExtension::highlight_color).
+    pub fallback_color: Color,
+    /// Width of the outline, in pixels.
+    pub width: f32,
+    /// Opacity of the overlay.
+    pub strength: f32,
+    /// ID of the only sprite or shape to highlight, the whole animation if `None`.
+    pub part: Option<i16>,
+}
+
+impl Default for Highlight {
+    #[inline]
+    fn default() -> Self {
+        Highlight {
+            mode: HighlightMode::default(),
+            fallback_color: DEFAULT_HIGHLIGHT_COLOR,
+            width: 2.,
+            strength: 0.5,
+            part: None,
+        }
+    }
+}
+
+impl Highlight {
+    /// The highlight color of the animation, or the fallback color.
+    pub fn color(&self, animation: &Animation) -> Color {
+        animation
+            .index
+            .as_ref()
+            .and_then(|index| index.extension.as_ref()?.highlight_color.clone())
+            .unwrap_or_else(|| self.fallback_color.clone())
+    }
+}
+
+/// A backend drawing shapes in a single color, keeping their transparency.
+#[derive(Debug)]
+pub struct Silhouette<'a, R> {
+    backend: &'a mut R,
+    color: ColorTransform,
+    part: Option<i16>,
+    in_part: usize,
+}
+
+impl<'a, R: Render> Silhouette<'a, R> {
+    /// Creates a backend drawing shapes in `color`, with their opacity multiplied by `alpha`.
+    /// Only the shapes under `part` are drawn if it is set.
+    #[inline]
+    pub fn new(backend: &'a mut R, color: &Color, alpha: f32, part: Option<i16>) -> Self {
+        let color = ColorTransform::Multiply(0., 0., 0., color.alpha * alpha)
+            .combine(&ColorTransform::Add(color.red, color.green, color.blue, 0.));
+        Self {
+            backend,
+            color,
+            part,
+            in_part: 0,
+        }
+    }
+}
+
+impl<R: Render> Render for Silhouette<'_, R> {
+    fn render(&mut self, shape: &Shape, transform: SpriteTransform) {
+        if self.part.is_some() && self.in_part == 0 {
+            return;
+        }
+        let transform = SpriteTransform {
+            color: transform.color.combine(&self.color),
+            ..transform
+        };
+        self.backend.render(shape, transform);
+    }
+
+    #[inline]
+    fn sprite_transform(&mut self, sprite: &Sprite, transform: SpriteTransform) -> SpriteTransform {
+        self.backend.sprite_transform(sprite, transform)
+    }
+
+    #[inline]
+    fn set_atlas(&mut self, atlas: usize) {
+        self.backend.set_atlas(atlas);
+    }
+
+    fn enter(&mut self, id: i16) {
+        self.in_part += usize::from(self.part == Some(id));
+        self.backend.enter(id);
+    }
+
+    fn leave(&mut self, id: i16) {
+        self.in_part -= usize::from(self.part == Some(id));
+        self.backend.leave(id);
+    }
+}
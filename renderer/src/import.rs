@@ -0,0 +1,337 @@
+//! Creation of animations from sequences of images.
+//!
+//! Frames are trimmed and deduplicated, so that every distinct image becomes a single
+//! [`Shape`] in a packed atlas. The frames are then played by one `Indexed` sprite placing the
+//! right shape at the right position on each frame.
+use hashbrown::HashMap;
+use image::{RgbaImage, imageops};
+
+use crate::atlas::{self, PackOptions, Region};
+use crate::types::{
+    Animation, AnimationVersion, FrameData, Shape, Sprite, SpriteFlags, SpriteName, SpritePayload,
+    Texture, TransformTable, strip_direction,
+};
+
+const USE_ATLAS_AND_TRANSFORM_INDEX: u8 = 0x11;
+const HAS_NAME: u8 = 0x40;
+const TRANSLATION_TAG: u32 = 2;
+
+/// Options for [`from_frames`].
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    /// Name of the sprite playing the frames.
+    pub name: String,
+    /// Name of the texture the atlas will be saved as.
+    pub texture: String,
+    pub frame_rate: u8,
+    /// Position of the sprite origin within the frames, in pixels from the top left corner.
+    /// Defaults to the bottom center of the first frame.
+    pub origin: Option<(f32, f32)>,
+    pub pack: PackOptions,
+}
+
+impl Default for ImportOptions {
+    #[inline]
+    fn default() -> Self {
+        ImportOptions {
+            name: "1_AnimStatique".to_owned(),
+            texture: "atlas".to_owned(),
+            frame_rate: 30,
+            origin: None,
+            pack: PackOptions::default(),
+        }
+    }
+}
+
+/// Builds an animation playing the given frames, along with its atlas.
+pub fn from_frames(frames: &[RgbaImage], options: &ImportOptions) -> (Animation, RgbaImage) {
+    let origin = options.origin.unwrap_or_else(|| {
+        frames.first().map_or((0., 0.), |frame| {
+            (frame.width() as f32 / 2., frame.height() as f32)
+        })
+    });
+
+    let mut images: Vec<RgbaImage> = vec![];
+    let mut placements = Vec::with_capacity(frames.len());
+    for frame in frames {
+        let trimmed = if options.pack.trim {
+            atlas::trim(frame)
+        } else {
+            Region {
+                x: 0,
+                y: 0,
+                width: frame.width(),
+                height: frame.height(),
+            }
+        };
+        let image = imageops::crop_imm(frame, trimmed.x, trimmed.y, trimmed.width, trimmed.height)
+            .to_image();
+        let index = if let Some(index) = images.iter().position(|existing| *existing == image) {
+            index
+        } else {
+            images.push(image);
+            images.len() - 1
+        };
+        let translation = (trimmed.x as f32 - origin.0, trimmed.y as f32 - origin.1);
+        placements.push((index as i16, translation));
+    }
+
+    let (atlas, regions) = atlas::pack(&images, &options.pack);
+    let mut shapes = HashMap::with_capacity(regions.len());
+    for (id, region) in regions.into_iter().enumerate() {
+        let mut shape = Shape {
+            id: id as i16,
+            texture_index: 0,
+            top: 0.,
+            left: 0.,
+            bottom: 0.,
+            right: 0.,
+            width: region.width as u16,
+            height: region.height as u16,
+            offset_x: 0.,
+            offset_y: 0.,
+        };
+        atlas::set_region(&mut shape, region, atlas.width(), atlas.height());
+        shapes.insert(shape.id, shape);
+    }
+
+    // every shape gets a one-element list of children that frames can point to
+    let sprite_info = (0..shapes.len() as i16)
+        .flat_map(|id| [1, id])
+        .collect::<Vec<_>>();
+    let mut translations: Vec<f32> = vec![];
+    let mut translation_offsets = HashMap::new();
+    let mut frame_data = vec![];
+    let mut frame_pos = vec![];
+    for (shape_id, (x, y)) in placements {
+        let offset = *translation_offsets
+            .entry((x.to_bits(), y.to_bits()))
+            .or_insert_with(|| {
+                translations.extend([x, y]);
+                translations.len() - 2
+            });
+        frame_pos.extend([frame_data.len() as i32, i32::from(shape_id) * 2]);
+        frame_data.extend([TRANSLATION_TAG, offset as u32]);
+    }
+
+    let id = shapes.len() as i16;
+    let sprite = Sprite {
+        id,
+        name: SpriteName {
+            name: Some(options.name.clone()),
+            name_crc: crc32(&options.name),
+            base_name_crc: crc32(strip_direction(&options.name)),
+        },
+        flags: SpriteFlags(HAS_NAME),
+        frame_data: frame_data_of(frame_data),
+        payload: SpritePayload::Indexed(frame_pos, sprite_info, vec![]),
+    };
+
+    let animation = Animation {
+        version: AnimationVersion(USE_ATLAS_AND_TRANSFORM_INDEX),
+        header: 0,
+        frame_rate: options.frame_rate,
+        index: None,
+        texture: Some(Texture {
+            name: options.texture.clone(),
+            crc: crc32(&options.texture),
+        }),
+        shapes,
+        transform: Some(TransformTable {
+            colors: vec![],
+            rotations: vec![],
+            translations,
+            actions: vec![],
+        }),
+        sprites: HashMap::from([(id, sprite)]),
+        imports: vec![],
+        shape_order: vec![],
+        sprite_order: vec![],
+    };
+    (animation, atlas)
+}
+
+/// Splits a sheet into a grid of equally sized cells, row by row. Trailing cells that are fully
+/// transparent are dropped, since grids are rarely filled completely.
+pub fn split_grid(sheet: &RgbaImage, columns: u32, rows: u32) -> Vec<RgbaImage> {
+    let width = sheet.width() / columns.max(1);
+    let height = sheet.height() / rows.max(1);
+    let mut frames = (0..rows)
+        .flat_map(|row| (0..columns).map(move |column| (column, row)))
+        .map(|(column, row)| {
+            imageops::crop_imm(sheet, column * width, row * height, width, height).to_image()
+        })
+        .collect::<Vec<_>>();
+    while frames
+        .last()
+        .is_some_and(|frame| frame.pixels().all(|pixel| pixel[3] == 0))
+    {
+        frames.pop();
+    }
+    frames
+}
+
+/// Splits a sheet described by a TexturePacker JSON descriptor, in either the array or the hash
+/// format. Trimmed frames are restored to their source size so that they stay aligned.
+#[cfg(feature = "serde")]
+pub fn split_descriptor(sheet: &RgbaImage, descriptor: &str) -> std::io::Result<Vec<RgbaImage>> {
+    use serde_json::Value;
+
+    let invalid = |msg: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, msg.to_owned());
+    let descriptor: Value = serde_json::from_str(descriptor)?;
+    let frames = match &descriptor["frames"] {
+        Value::Array(frames) => frames.iter().collect::<Vec<_>>(),
+        Value::Object(frames) => {
+            let mut entries = frames.iter().collect::<Vec<_>>();
+            entries.sort_by_key(|(name, _)| natural_key(name));
+            entries.into_iter().map(|(_, frame)| frame).collect()
+        }
+        _ => return Err(invalid("descriptor has no frames")),
+    };
+
+    let get = |value: &Value, key: &str| u32::try_from(value.get(key)?.as_u64()?).ok();
+    let size = |value: &Value| Some((get(value, "w")?, get(value, "h")?));
+    let rect = |value: &Value| Some((get(value, "x")?, get(value, "y")?, size(value)?));
+    frames
+        .into_iter()
+        .map(|frame| {
+            let (x, y, (w, h)) =
+                rect(&frame["frame"]).ok_or_else(|| invalid("invalid frame rectangle"))?;
+            let rotated = frame["rotated"].as_bool().unwrap_or(false);
+            let image = if rotated {
+                // rotated frames are stored turned clockwise, with their size unrotated
+                imageops::rotate270(&imageops::crop_imm(sheet, x, y, h, w).to_image())
+            } else {
+                imageops::crop_imm(sheet, x, y, w, h).to_image()
+            };
+
+            let source = size(&frame["sourceSize"]);
+            let placed = rect(&frame["spriteSourceSize"]);
+            match (source, placed) {
+                (Some((source_w, source_h)), Some((px, py, ..))) => {
+                    let mut restored = RgbaImage::new(source_w, source_h);
+                    imageops::replace(&mut restored, &image, px.into(), py.into());
+                    Ok(restored)
+                }
+                _ => Ok(image),
+            }
+        })
+        .collect()
+}
+
+/// Orders names such as `walk_2` before `walk_10`.
+pub fn natural_key(name: &str) -> (String, u64) {
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    let digits = stem.len() - stem.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    let (prefix, number) = stem.split_at(stem.len() - digits);
+    (prefix.to_owned(), number.parse().unwrap_or(0))
+}
+
+fn frame_data_of(values: Vec<u32>) -> FrameData {
+    let max = values.iter().copied().max().unwrap_or(0);
+    if max < u32::from(u8::MAX) {
+        FrameData::Bytes(values.into_iter().map(|value| value as u8).collect())
+    } else if max < u32::from(u16::MAX) {
+        FrameData::Shorts(values.into_iter().map(|value| value as u16).collect())
+    } else {
+        FrameData::Ints(values)
+    }
+}
+
+/// Computes the CRC-32 the game uses to look up names.
+pub(crate) fn crc32(str: &str) -> i32 {
+    let mut crc = u32::MAX;
+    for byte in str.bytes() {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+    use crate::timeline;
+
+    fn frame(x: u32, y: u32, value: u8) -> RgbaImage {
+        let mut frame = RgbaImage::new(8, 8);
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            frame.put_pixel(x + dx, y + dy, Rgba([value, 0, 0, 255]));
+        }
+        frame
+    }
+
+    #[test]
+    fn builds_a_sprite_placing_deduplicated_frames() {
+        let frames = [frame(1, 1, 1), frame(4, 5, 2), frame(3, 2, 1)];
+        let (animation, atlas) = from_frames(&frames, &ImportOptions::default());
+
+        // the first and last frames only differ by their position
+        assert_eq!(animation.shapes.len(), 2);
+        let (parts, _) = atlas::slice(&animation, &atlas);
+        for (part, value) in parts.iter().zip([1, 2]) {
+            assert_eq!(
+                part.image,
+                RgbaImage::from_pixel(2, 2, Rgba([value, 0, 0, 255]))
+            );
+        }
+
+        let sprite = &animation.sprites[&2];
+        assert_eq!(sprite.name.name.as_deref(), Some("1_AnimStatique"));
+        assert_eq!(sprite.frame_count(), 3);
+        // placed relative to the bottom center of the frames
+        let placed = (0..3)
+            .map(|frame| {
+                let [placement] = &timeline::placements(&animation, sprite, frame)[..] else {
+                    panic!("expected a single placement on frame {}", frame);
+                };
+                let position = placement.transform.position;
+                (placement.id, position.m31, position.m32)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(placed, [(0, -3., -7.), (1, 0., -3.), (0, -1., -6.)]);
+    }
+
+    #[test]
+    fn splits_grids_row_by_row() {
+        let mut sheet = RgbaImage::new(6, 4);
+        for (i, (x, y)) in [(0, 0), (3, 0), (0, 2)].into_iter().enumerate() {
+            sheet.put_pixel(x, y, Rgba([i as u8, 0, 0, 255]));
+        }
+        let frames = split_grid(&sheet, 2, 2);
+
+        // the last cell is empty and dropped
+        assert_eq!(frames.len(), 3);
+        for (i, frame) in frames.iter().enumerate() {
+            assert_eq!((frame.width(), frame.height()), (3, 2));
+            assert_eq!(frame.get_pixel(0, 0), &Rgba([i as u8, 0, 0, 255]));
+        }
+    }
+
+    #[test]
+    fn keeps_empty_cells_between_frames() {
+        let mut sheet = RgbaImage::new(3, 1);
+        sheet.put_pixel(2, 0, Rgba([0, 0, 0, 255]));
+
+        assert_eq!(split_grid(&sheet, 3, 1).len(), 3);
+        assert!(split_grid(&RgbaImage::new(3, 1), 3, 1).is_empty());
+    }
+
+    #[test]
+    fn orders_names_by_their_trailing_number() {
+        let mut names = ["walk_10.png", "walk_2.png", "idle.png", "walk_1"];
+        names.sort_by_key(|name| natural_key(name));
+
+        assert_eq!(names, ["idle.png", "walk_1", "walk_2.png", "walk_10.png"]);
+        assert_eq!(natural_key("run12.png"), ("run".to_owned(), 12));
+        assert_eq!(natural_key("v1.2.png"), ("v1.".to_owned(), 2));
+    }
+}
@@ -0,0 +1,350 @@
+//! Animations whose sprites are decoded on demand.
+//!
+//! Sprites make up most of an animation file, so [`LazyAnimation`] only records where each of
+//! them is stored when it is opened, and decodes one the first time it is requested.
+use std::io;
+use std::ops::Range;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+
+use hashbrown::HashMap;
+
+use crate::decode::{DecodeExt, DecodeOptions, DecodeWarning, Decoder, SpriteEntry};
+use crate::player::STATIC_ANIMATION;
+use crate::timeline;
+use crate::types::{Action, Animation, Sprite, SpriteFlags, SpriteName, strip_direction};
+
+/// An animation with sprites decoded the first time they are requested.
+#[derive(Debug)]
+pub struct LazyAnimation {
+    data: Vec<u8>,
+    options: DecodeOptions,
+    head: Animation,
+    sprites: HashMap<i16, LazySprite>,
+    /// The sprites built so far by [`LazyAnimation::animation_for`], shared by its results.
+    loaded: Mutex<Arc<Animation>>,
+    warnings: Mutex<Vec<DecodeWarning>>,
+}
+
+#[derive(Debug)]
+struct LazySprite {
+    tag: i8,
+    flags: SpriteFlags,
+    name: SpriteName,
+    /// The payload and frame data following the header.
+    range: Range<usize>,
+    sprite: OnceLock<Sprite>,
+}
+
+impl LazyAnimation {
+    /// Indexes the sprites of an animation file, decoding everything else.
+    pub fn new(data: Vec<u8>, options: DecodeOptions) -> io::Result<LazyAnimation> {
+        let mut decoder = Decoder::new(options);
+        let mut cursor = io::Cursor::new(&data[..]);
+        let mut head = decoder.decode_head(&mut cursor)?;
+
+        let sprite_count = cursor.decode::<u16>()?;
+        let mut sprites = HashMap::with_capacity(sprite_count.into());
+        let mut truncated = false;
+        for index in 0..sprite_count {
+            let (tag, id, flags, name) = decoder.decode_sprite_header(&mut cursor)?;
+            let start = cursor.position() as usize;
+            if !skip_payload(&mut cursor, tag)? {
+                if !options.lenient {
+                    return Err(io::Error::other(format!("Unexpected case: {}", tag)));
+                }
+                decoder.push_warning(DecodeWarning::UnknownSpriteTag {
+                    sprite: id,
+                    tag,
+                    missing: usize::from(sprite_count - index),
+                });
+                truncated = true;
+                break;
+            }
+            // the tag of frame data is the size of its values in bytes
+            let frame_tag = cursor.decode::<u8>()?;
+            let known = matches!(frame_tag, 1 | 2 | 4);
+            if !known && !options.lenient {
+                return Err(io::Error::other(format!("Unexpected case: {}", frame_tag)));
+            }
+            let size = cursor.decode::<u32>()?;
+            skip(&mut cursor, u64::from(size) * u64::from(frame_tag))?;
+            if !known {
+                decoder.push_warning(DecodeWarning::UnknownFrameDataTag {
+                    sprite: id,
+                    tag: frame_tag,
+                });
+                continue;
+            }
+            let sprite = LazySprite {
+                tag,
+                flags,
+                name,
+                range: start..cursor.position() as usize,
+                sprite: OnceLock::new(),
+            };
+            head.sprite_order.push(id);
+            sprites.insert(id, sprite);
+        }
+        if !truncated {
+            head.imports = decoder.decode_imports(&mut cursor)?;
+        }
+
+        Ok(LazyAnimation {
+            data,
+            options,
+            loaded: Mutex::new(Arc::new(head.clone())),
+            head,
+            sprites,
+            warnings: Mutex::new(decoder.take_warnings()),
+        })
+    }
+
+    /// Reads a whole animation file and indexes its sprites.
+    pub fn read<R: io::Read>(reader: &mut R, options: DecodeOptions) -> io::Result<LazyAnimation> {
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+        LazyAnimation::new(data, options)
+    }
+
+    /// The animation without any of its sprites.
+    #[inline]
+    pub fn head(&self) -> &Animation {
+        &self.head
+    }
+
+    /// Takes the warnings collected while indexing the sprites and decoding them since the last
+    /// call.
+    #[inline]
+    pub fn take_warnings(&self) -> Vec<DecodeWarning> {
+        std::mem::take(&mut self.warnings.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    pub fn sprite_ids(&self) -> impl Iterator<Item = i16> + '_ {
+        self.sprites.keys().copied()
+    }
+
+    /// The name of a sprite, available without decoding it.
+    #[inline]
+    pub fn sprite_name(&self, id: i16) -> Option<&SpriteName> {
+        Some(&self.sprites.get(&id)?.name)
+    }
+
+    #[inline]
+    pub fn is_decoded(&self, id: i16) -> bool {
+        self.sprites
+            .get(&id)
+            .is_some_and(|entry| entry.sprite.get().is_some())
+    }
+
+    /// Returns a sprite, decoding it if it is requested for the first time.
+    pub fn sprite(&self, id: i16) -> io::Result<Option<&Sprite>> {
+        let Some(entry) = self.sprites.get(&id) else {
+            return Ok(None);
+        };
+        if let Some(sprite) = entry.sprite.get() {
+            return Ok(Some(sprite));
+        }
+        let sprite = self.decode_sprite(id, entry)?;
+        Ok(Some(entry.sprite.get_or_init(|| sprite)))
+    }
+
+    /// Returns an animation with a sprite, all the sprites nested in it and all the sprites its
+    /// actions jump to, which is enough to play it. The sprites are added to those of previous
+    /// calls, so that the same animation is shared until a new sprite is requested.
+    pub fn animation_for(&self, id: i16) -> io::Result<Arc<Animation>> {
+        let mut loaded = self.loaded.lock().unwrap_or_else(PoisonError::into_inner);
+        // the loaded sprites always come with the sprites they need
+        if loaded.sprites.contains_key(&id) {
+            return Ok(Arc::clone(&loaded));
+        }
+        let mut animation = Animation::clone(&loaded);
+        let mut pending = vec![id];
+        while let Some(id) = pending.pop() {
+            if animation.sprites.contains_key(&id) {
+                continue;
+            }
+            let Some(sprite) = self.sprite(id)? else {
+                continue;
+            };
+            pending.extend(timeline::parts(&self.head, sprite));
+            pending.extend(self.jump_targets(sprite));
+            animation.sprites.insert(id, sprite.clone());
+        }
+        *loaded = Arc::new(animation);
+        Ok(Arc::clone(&loaded))
+    }
+
+    /// Decodes all the remaining sprites, returning the warnings that were not taken yet.
+    pub fn into_animation(mut self) -> io::Result<(Animation, Vec<DecodeWarning>)> {
+        let entries = std::mem::take(&mut self.sprites);
+        let mut sprites = HashMap::with_capacity(entries.len());
+        for (id, mut entry) in entries {
+            let sprite = match entry.sprite.take() {
+                Some(sprite) => sprite,
+                None => self.decode_sprite(id, &entry)?,
+            };
+            sprites.insert(id, sprite);
+        }
+        let warnings = self.take_warnings();
+        let animation = Animation {
+            sprites,
+            ..self.head
+        };
+        Ok((animation, warnings))
+    }
+
+    /// Finds the sprites that the actions of a sprite may jump to, in any direction.
+    fn jump_targets(&self, sprite: &Sprite) -> Vec<i16> {
+        let mut names = vec![];
+        for frame in 0..sprite.frame_count() as u32 {
+            for action in timeline::actions(&self.head, sprite, frame) {
+                match action {
+                    Action::GoTo(name, _) => names.push(name.as_str()),
+                    Action::GoToStatic => names.push(STATIC_ANIMATION),
+                    Action::GoToRandom(targets, _) => {
+                        names.extend(targets.iter().map(String::as_str));
+                    }
+                    Action::GoToIfPrevious(_, next, default) => {
+                        names.extend(next.iter().chain(default).map(String::as_str));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        self.sprites
+            .iter()
+            .filter(|(_, entry)| {
+                entry.name.name.as_deref().is_some_and(|name| {
+                    let name = strip_direction(name);
+                    names.iter().any(|target| strip_direction(target) == name)
+                })
+            })
+            .map(|(&id, _)| id)
+            .collect()
+    }
+
+    fn decode_sprite(&self, id: i16, entry: &LazySprite) -> io::Result<Sprite> {
+        let mut cursor = &self.data[entry.range.clone()];
+        let mut decoder = Decoder::new(self.options);
+        let sprite = decoder.decode_sprite_body(
+            &mut cursor,
+            entry.tag,
+            id,
+            entry.flags.clone(),
+            entry.name.clone(),
+        );
+        self.warnings
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .append(&mut decoder.take_warnings());
+        match sprite? {
+            SpriteEntry::Sprite(sprite) => Ok(sprite),
+            SpriteEntry::Dropped | SpriteEntry::Unreadable => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "indexed sprite could not be decoded",
+            )),
+        }
+    }
+}
+
+/// Skips the payload of a sprite, returning `false` if its tag is unknown.
+fn skip_payload(cursor: &mut io::Cursor<&[u8]>, tag: i8) -> io::Result<bool> {
+    match tag {
+        1 => {
+            skip(cursor, 2)?;
+            skip_prefixed(cursor, 2)?;
+        }
+        2 => skip(cursor, 2)?,
+        3 => {
+            skip_prefixed(cursor, 2)?;
+            skip_prefixed(cursor, 2)?;
+        }
+        4 => {
+            skip_prefixed(cursor, 4)?;
+            skip_prefixed(cursor, 2)?;
+            skip_prefixed(cursor, 2)?;
+        }
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// Skips an array prefixed by its length as a `u16`.
+fn skip_prefixed(cursor: &mut io::Cursor<&[u8]>, width: u64) -> io::Result<()> {
+    let count = cursor.decode::<u16>()?;
+    skip(cursor, u64::from(count) * width)
+}
+
+fn skip(cursor: &mut io::Cursor<&[u8]>, len: u64) -> io::Result<()> {
+    let position = cursor.position() + len;
+    if position > cursor.get_ref().len() as u64 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    cursor.set_position(position);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::StringEncoding;
+    use crate::encode::EncodeExt;
+    use crate::fixtures;
+
+    fn encoded() -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.encode(&fixtures::animation()).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn includes_parts_and_jump_targets() {
+        let lazy = LazyAnimation::new(encoded(), DecodeOptions::default()).unwrap();
+        let animation = lazy.animation_for(fixtures::ROOT).unwrap();
+        let mut ids = animation.sprites.keys().copied().collect::<Vec<_>>();
+        ids.sort_unstable();
+        assert_eq!(ids, [10, 11, 12, fixtures::ROOT, fixtures::WALK]);
+
+        let lazy = LazyAnimation::new(encoded(), DecodeOptions::default()).unwrap();
+        let animation = lazy.animation_for(fixtures::WALK).unwrap();
+        assert_eq!(animation.sprites.len(), 1);
+        assert!(!lazy.is_decoded(fixtures::ROOT));
+    }
+
+    #[test]
+    fn shares_the_animation_until_a_new_sprite_is_requested() {
+        let lazy = LazyAnimation::new(encoded(), DecodeOptions::default()).unwrap();
+        let walk = lazy.animation_for(fixtures::WALK).unwrap();
+        assert!(Arc::ptr_eq(
+            &walk,
+            &lazy.animation_for(fixtures::WALK).unwrap()
+        ));
+
+        let root = lazy.animation_for(fixtures::ROOT).unwrap();
+        assert!(!Arc::ptr_eq(&walk, &root));
+        assert!(Arc::ptr_eq(&root, &lazy.animation_for(10).unwrap()));
+    }
+
+    #[test]
+    fn reports_warnings_once() {
+        let mut bytes = encoded();
+        let name = bytes
+            .windows(6)
+            .position(|window| window == b"1_Part")
+            .unwrap();
+        bytes[name + 1] = 0xE9;
+        let options = DecodeOptions {
+            strings: StringEncoding::Latin1,
+            ..DecodeOptions::default()
+        };
+        let lazy = LazyAnimation::new(bytes, options).unwrap();
+        assert_eq!(
+            lazy.take_warnings(),
+            [DecodeWarning::InvalidString("1\u{e9}Part".to_owned())]
+        );
+
+        let sprite = lazy.sprite(10).unwrap().unwrap();
+        assert_eq!(sprite.name.name.as_deref(), Some("1\u{e9}Part"));
+        assert!(lazy.take_warnings().is_empty());
+    }
+}
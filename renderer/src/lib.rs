@@ -1,8 +1,28 @@
+#[cfg(test)]
+mod fixtures;
 mod frame_reader;
 
+pub mod atlas;
+pub mod colors;
 pub mod decode;
+pub mod diff;
+pub mod encode;
+pub mod godot;
+pub mod highlight;
+pub mod import;
+pub mod lazy;
 #[cfg(feature = "notan")]
 pub mod notan;
+pub mod overrides;
 pub mod player;
+pub mod random;
 pub mod render;
+pub mod scene;
+#[cfg(feature = "zip")]
+pub mod source;
+#[cfg(feature = "serde")]
+pub mod spine;
+#[cfg(feature = "serde")]
+pub mod text;
+pub mod timeline;
 pub mod types;
@@ -10,12 +10,27 @@ use crate::types::Shape;
 pub struct NotanBackend {
     draw: Draw,
     atlas: Texture,
+    /// Atlases registered with [`NotanBackend::add_atlas`], starting at index 1.
+    extra_atlases: Vec<Texture>,
+    current_atlas: usize,
 }
 
 impl NotanBackend {
     #[inline]
     pub fn new(draw: Draw, atlas: Texture) -> Self {
-        Self { draw, atlas }
+        Self {
+            draw,
+            atlas,
+            extra_atlases: vec![],
+            current_atlas: 0,
+        }
+    }
+
+    /// Registers another atlas and returns the index to select it with.
+    #[inline]
+    pub fn add_atlas(&mut self, atlas: Texture) -> usize {
+        self.extra_atlases.push(atlas);
+        self.extra_atlases.len()
     }
 
     #[inline]
@@ -38,28 +53,31 @@ impl NotanBackend {
         Self {
             draw,
             atlas: self.atlas.clone(),
+            extra_atlases: self.extra_atlases.clone(),
+            current_atlas: 0,
         }
     }
 }
 
 impl Render for NotanBackend {
     fn render(&mut self, shape: &Shape, transform: SpriteTransform) {
+        let atlas = match self.current_atlas {
+            0 => &self.atlas,
+            i => self.extra_atlases.get(i - 1).unwrap_or(&self.atlas),
+        };
         let [x0, y0, x1, y1, x2, y2] = transform.position.to_array();
         let mat = Mat3::from_cols_array(&[x0, y0, 0., x1, y1, 0., x2, y2, 0.]);
         let color = transform.color.into_color();
 
         self.draw
-            .image(&self.atlas)
+            .image(atlas)
             .position(shape.offset_x, shape.offset_y)
             .size(shape.width as _, shape.height as _)
             .crop(
+                (shape.left * atlas.width(), shape.top * atlas.height()),
                 (
-                    shape.left * self.atlas.width(),
-                    shape.top * self.atlas.height(),
-                ),
-                (
-                    (shape.right - shape.left) * self.atlas.width(),
-                    (shape.bottom - shape.top) * self.atlas.height(),
+                    (shape.right - shape.left) * atlas.width(),
+                    (shape.bottom - shape.top) * atlas.height(),
                 ),
             )
             .flip_y(true)
@@ -67,4 +85,9 @@ impl Render for NotanBackend {
             .blend_mode(BlendMode::OVER)
             .color(<[f32; 4]>::from(color).into());
     }
+
+    #[inline]
+    fn set_atlas(&mut self, atlas: usize) {
+        self.current_atlas = atlas;
+    }
 }
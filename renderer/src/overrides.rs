@@ -0,0 +1,263 @@
+//! Substitution of parts of an animation with parts of other animations.
+//!
+//! Characters are made of a body animation combined with equipment animations, each with its
+//! own atlas. [`PartOverrides`] maps sprites or shapes of the rendered animation to
+//! replacements taken from other animations, and [`Overridden`] draws the replacements in
+//! place of the originals while traversing the hierarchy.
+use std::sync::Arc;
+
+use hashbrown::HashMap;
+
+use crate::render::{Render, SpriteTransform};
+use crate::timeline::Tween;
+use crate::types::{Animation, Shape, Sprite};
+
+/// Identifies a sprite or shape of the rendered animation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PartKey {
+    /// A sprite or shape ID.
+    Id(i16),
+    /// The CRC of a sprite name, as in [`SpriteName::name_crc`](crate::types::SpriteName).
+    NameCrc(i32),
+}
+
+/// A sprite or shape of one of the override sources.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Substitute {
+    /// Index of the source, as returned by [`PartOverrides::add_source`].
+    pub source: usize,
+    pub id: i16,
+}
+
+/// An animation substitutes are taken from.
+#[derive(Debug, Clone)]
+pub struct OverrideSource {
+    pub animation: Arc<Animation>,
+    /// Index of the atlas of the animation in the backend, see [`Render::set_atlas`].
+    pub atlas: usize,
+}
+
+/// Substitutes to draw in place of parts of an animation.
+#[derive(Debug, Clone, Default)]
+pub struct PartOverrides {
+    sources: Vec<OverrideSource>,
+    parts: HashMap<PartKey, Substitute>,
+}
+
+impl PartOverrides {
+    #[inline]
+    pub fn new() -> PartOverrides {
+        PartOverrides::default()
+    }
+
+    /// Registers an animation to take substitutes from and returns its index.
+    #[inline]
+    pub fn add_source(&mut self, animation: Arc<Animation>, atlas: usize) -> usize {
+        self.sources.push(OverrideSource { animation, atlas });
+        self.sources.len() - 1
+    }
+
+    #[inline]
+    pub fn sources(&self) -> &[OverrideSource] {
+        &self.sources
+    }
+
+    #[inline]
+    pub fn set(&mut self, key: PartKey, substitute: Substitute) {
+        self.parts.insert(key, substitute);
+    }
+
+    /// Substitutes a part with the sprite of a source that has the given name CRC. Returns
+    /// `false` if the source has no such sprite.
+    pub fn set_by_name(&mut self, key: PartKey, source: usize, name_crc: i32) -> bool {
+        let Some(sprite) = self.sources.get(source).and_then(|source| {
+            source
+                .animation
+                .sprites
+                .values()
+                .find(|sprite| sprite.name.name_crc == name_crc)
+        }) else {
+            return false;
+        };
+        let id = sprite.id;
+        self.set(key, Substitute { source, id });
+        true
+    }
+
+    #[inline]
+    pub fn remove(&mut self, key: PartKey) -> Option<Substitute> {
+        self.parts.remove(&key)
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.parts.clear();
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.parts.is_empty()
+    }
+
+    /// Finds the substitute of a sprite or shape, looking it up by ID first and then by the
+    /// name of the sprite.
+    pub fn resolve(&self, animation: &Animation, id: i16) -> Option<(&OverrideSource, i16)> {
+        let substitute = self.parts.get(&PartKey::Id(id)).or_else(|| {
+            let sprite = animation.sprites.get(&id)?;
+            self.parts.get(&PartKey::NameCrc(sprite.name.name_crc))
+        })?;
+        let source = self.sources.get(substitute.source)?;
+        Some((source, substitute.id))
+    }
+}
+
+/// A backend drawing the substitutes of [`PartOverrides`] in place of the original parts.
+///
+/// Substitutes play at the local frame of the part they replace. Overrides only apply to the
+/// rendered animation, so the children of a substitute are drawn as they are.
+#[derive(Debug)]
+pub struct Overridden<'a, R> {
+    backend: &'a mut R,
+    overrides: &'a PartOverrides,
+    atlas: usize,
+    substituted: bool,
+}
+
+impl<'a, R: Render> Overridden<'a, R> {
+    #[inline]
+    pub fn new(backend: &'a mut R, overrides: &'a PartOverrides) -> Self {
+        Self::with_atlas(backend, overrides, 0)
+    }
+
+    /// Creates a backend for an animation drawn from an atlas other than the first one.
+    #[inline]
+    pub fn with_atlas(backend: &'a mut R, overrides: &'a PartOverrides, atlas: usize) -> Self {
+        Self {
+            backend,
+            overrides,
+            atlas,
+            substituted: false,
+        }
+    }
+
+    fn substitute(&self, animation: &Animation, id: i16) -> Option<(&'a OverrideSource, i16)> {
+        if self.substituted {
+            return None;
+        }
+        self.overrides.resolve(animation, id)
+    }
+
+    fn with_source(&mut self, source: &OverrideSource, render: impl FnOnce(&mut Self)) {
+        let previous = self.atlas;
+        self.substituted = true;
+        self.set_atlas(source.atlas);
+        render(self);
+        self.set_atlas(previous);
+        self.substituted = false;
+    }
+}
+
+impl<R: Render> Render for Overridden<'_, R> {
+    #[inline]
+    fn render(&mut self, shape: &Shape, transform: SpriteTransform) {
+        self.backend.render(shape, transform);
+    }
+
+    #[inline]
+    fn sprite_transform(&mut self, sprite: &Sprite, transform: SpriteTransform) -> SpriteTransform {
+        self.backend.sprite_transform(sprite, transform)
+    }
+
+    #[inline]
+    fn set_atlas(&mut self, atlas: usize) {
+        self.atlas = atlas;
+        self.backend.set_atlas(atlas);
+    }
+
+    #[inline]
+    fn enter(&mut self, id: i16) {
+        self.backend.enter(id);
+    }
+
+    #[inline]
+    fn leave(&mut self, id: i16) {
+        self.backend.leave(id);
+    }
+
+    fn render_at(&mut self, anm: &Animation, id: i16, transform: SpriteTransform, frame: u32) {
+        self.enter(id);
+        if let Some((source, id)) = self.substitute(anm, id) {
+            self.with_source(source, |this| {
+                this.render_at(&source.animation, id, transform, frame);
+            });
+        } else if let Some(sprite) = anm.sprites.get(&id) {
+            self.render_sprite(anm, sprite, transform, frame);
+        } else if let Some(shape) = anm.shapes.get(&id) {
+            self.render(shape, transform);
+        }
+        self.leave(id);
+    }
+
+    fn render_at_tweened(
+        &mut self,
+        anm: &Animation,
+        id: i16,
+        transform: SpriteTransform,
+        tween: Tween,
+    ) {
+        self.enter(id);
+        if let Some((source, id)) = self.substitute(anm, id) {
+            self.with_source(source, |this| {
+                this.render_at_tweened(&source.animation, id, transform, tween);
+            });
+        } else if let Some(sprite) = anm.sprites.get(&id) {
+            self.render_sprite_tweened(anm, sprite, transform, tween);
+        } else if let Some(shape) = anm.shapes.get(&id) {
+            self.render(shape, transform);
+        }
+        self.leave(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn overrides() -> (Animation, PartOverrides) {
+        let mut overrides = PartOverrides::new();
+        overrides.add_source(Arc::new(fixtures::animation()), 1);
+        (fixtures::animation(), overrides)
+    }
+
+    #[test]
+    fn substitutes_sprites_found_by_name() {
+        let (animation, mut overrides) = overrides();
+        assert!(overrides.set_by_name(PartKey::NameCrc(1000), 0, 1200));
+
+        let (source, id) = overrides.resolve(&animation, 10).unwrap();
+        assert_eq!((source.atlas, id), (1, 12));
+        assert!(overrides.resolve(&animation, 11).is_none());
+    }
+
+    #[test]
+    fn ignores_names_missing_from_the_source() {
+        let (animation, mut overrides) = overrides();
+        assert!(!overrides.set_by_name(PartKey::NameCrc(1000), 0, 4200));
+        assert!(!overrides.set_by_name(PartKey::NameCrc(1000), 1, 1200));
+
+        assert!(overrides.is_empty());
+        assert!(overrides.resolve(&animation, 10).is_none());
+    }
+
+    #[test]
+    fn resolves_ids_before_names() {
+        let (animation, mut overrides) = overrides();
+        assert!(overrides.set_by_name(PartKey::NameCrc(1000), 0, 1200));
+        assert!(overrides.set_by_name(PartKey::Id(10), 0, 1100));
+
+        assert_eq!(overrides.resolve(&animation, 10).unwrap().1, 11);
+        overrides.remove(PartKey::Id(10));
+        assert_eq!(overrides.resolve(&animation, 10).unwrap().1, 12);
+    }
+}
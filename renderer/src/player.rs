@@ -1,7 +1,37 @@
 use std::sync::Arc;
 
+use crate::colors::{ColorSlots, Recolored};
+use crate::highlight::{Highlight, HighlightMode, Silhouette};
+use crate::overrides::{Overridden, PartOverrides};
+use crate::random::SplitMix64;
 use crate::render::{Render, SpriteTransform};
-use crate::types::{Animation, Sprite};
+use crate::timeline::{self, Tween};
+use crate::types::{Action, Animation, Sprite, strip_direction};
+
+pub(crate) const STATIC_ANIMATION: &str = "AnimStatique";
+const HISTORY_LEN: usize = 16;
+
+/// An action reached by the player while passing a frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameEvent {
+    /// ID of the sprite carrying the action, either the current sprite or a nested one.
+    pub sprite: i16,
+    /// Local frame of that sprite.
+    pub frame: u32,
+    pub action: Action,
+}
+
+/// How playback continues when it reaches the end of the sprite or of the selected range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LoopMode {
+    /// Start over from the other end.
+    #[default]
+    Loop,
+    /// Stay on the last frame.
+    Once,
+    /// Turn around and play in the opposite direction.
+    PingPong,
+}
 
 #[derive(Debug)]
 pub struct AnimationPlayer<R> {
@@ -9,6 +39,19 @@ pub struct AnimationPlayer<R> {
     animation: Arc<Animation>,
     current_sprite: i16,
     frame: u32,
+    record_events: bool,
+    events: Vec<FrameEvent>,
+    follow_actions: bool,
+    stopped: bool,
+    rng: SplitMix64,
+    history: Vec<i16>,
+    paused: bool,
+    reverse: bool,
+    loop_mode: LoopMode,
+    range: Option<(u32, u32)>,
+    highlight: Option<Highlight>,
+    overrides: PartOverrides,
+    colors: ColorSlots,
 }
 
 impl<R> AnimationPlayer<R> {
@@ -19,28 +62,440 @@ impl<R> AnimationPlayer<R> {
             current_sprite: *animation.sprites.keys().next().unwrap(),
             animation,
             frame: 0,
+            record_events: false,
+            events: vec![],
+            follow_actions: false,
+            stopped: false,
+            rng: SplitMix64::default(),
+            history: vec![],
+            paused: false,
+            reverse: false,
+            loop_mode: LoopMode::Loop,
+            range: None,
+            highlight: None,
+            overrides: PartOverrides::new(),
+            colors: ColorSlots::new(),
         }
     }
 
+    /// Renders the current frame without advancing playback.
     pub fn render(&mut self, initial: SpriteTransform)
+    where
+        R: Render,
+    {
+        self.render_layered(initial, Tween::forward(self.frame, 0.));
+    }
+
+    /// Renders the current frame into another backend, without advancing playback. `atlas` is
+    /// the index of the atlas of the animation in that backend, see [`Render::set_atlas`].
+    pub fn render_with<B: Render>(&self, backend: &mut B, initial: SpriteTransform, atlas: usize) {
+        let tween = Tween::forward(self.frame, 0.);
+        self.layers(atlas).render(
+            backend,
+            &self.animation,
+            self.current_sprite(),
+            initial,
+            tween,
+        );
+    }
+
+    /// Advances playback by one frame unless it is paused.
+    #[inline]
+    pub fn tick(&mut self) {
+        if !self.paused {
+            self.advance(1);
+        }
+    }
+
+    /// Moves playback in the current direction, following the loop mode and range. The actions
+    /// of every frame passed are queued when events are recorded.
+    pub fn advance(&mut self, frames: u32) {
+        let animation = self.animation.clone();
+        for _ in 0..frames {
+            if self.stopped {
+                break;
+            }
+            let sprite = animation.sprites.get(&self.current_sprite).unwrap();
+            if self.record_events {
+                let triggered = timeline::triggered_actions(&animation, sprite, self.frame);
+                self.events
+                    .extend(triggered.into_iter().map(|triggered| FrameEvent {
+                        sprite: triggered.sprite,
+                        frame: triggered.frame,
+                        action: triggered.action.clone(),
+                    }));
+            }
+            if self.follow_actions {
+                let jump = timeline::actions(&animation, sprite, self.frame)
+                    .into_iter()
+                    .find_map(|action| self.resolve_jump(action));
+                match jump {
+                    Some(Jump::Sprite(id)) => {
+                        self.jump_to(id);
+                        continue;
+                    }
+                    Some(Jump::Stop) => {
+                        self.stopped = true;
+                        break;
+                    }
+                    None => {}
+                }
+            }
+            let (next, turned) = self.next_frame(self.reverse);
+            self.frame = next;
+            if turned {
+                self.reverse = !self.reverse;
+            }
+        }
+    }
+
+    /// Moves to the next frame, regardless of the direction and of whether playback is paused.
+    #[inline]
+    pub fn step_forward(&mut self) {
+        self.frame = self.next_frame(false).0;
+    }
+
+    /// Moves to the previous frame, regardless of the direction and of whether playback is
+    /// paused.
+    #[inline]
+    pub fn step_back(&mut self) {
+        self.frame = self.next_frame(true).0;
+    }
+
+    #[inline]
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    #[inline]
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    #[inline]
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    #[inline]
+    pub fn set_reverse(&mut self, reverse: bool) {
+        self.reverse = reverse;
+    }
+
+    #[inline]
+    pub fn is_reverse(&self) -> bool {
+        self.reverse
+    }
+
+    #[inline]
+    pub fn set_loop_mode(&mut self, mode: LoopMode) {
+        self.loop_mode = mode;
+    }
+
+    #[inline]
+    pub fn loop_mode(&self) -> LoopMode {
+        self.loop_mode
+    }
+
+    /// Restricts playback to an inclusive range of frames of the current sprite. The range is
+    /// reset when the sprite changes.
+    pub fn set_range(&mut self, range: Option<(u32, u32)>) {
+        self.range = range.map(|(start, end)| (start.min(end), start.max(end)));
+        let (start, end) = self.bounds();
+        let index = self.frame_index();
+        if index < start || index > end {
+            self.frame = start;
+        }
+    }
+
+    #[inline]
+    pub fn range(&self) -> Option<(u32, u32)> {
+        self.range
+    }
+
+    /// Position of playback within the frames of the current sprite.
+    #[inline]
+    pub fn frame_index(&self) -> u32 {
+        self.frame % self.frame_count()
+    }
+
+    #[inline]
+    fn frame_count(&self) -> u32 {
+        self.current_sprite().frame_count().max(1) as u32
+    }
+
+    /// The first and last frames playback can reach.
+    fn bounds(&self) -> (u32, u32) {
+        let last = self.frame_count() - 1;
+        match self.range {
+            Some((start, end)) => (start.min(last), end.min(last)),
+            None => (0, last),
+        }
+    }
+
+    /// Computes the frame following the current one in a direction, and whether playback
+    /// turned around.
+    fn next_frame(&self, backward: bool) -> (u32, bool) {
+        // the frame counter keeps growing while looping over the whole sprite, so that nested
+        // sprites keep their phase
+        if self.loop_mode == LoopMode::Loop && !backward && self.range.is_none() {
+            return (self.frame + 1, false);
+        }
+        let (start, end) = self.bounds();
+        let index = self.frame_index().clamp(start, end);
+        match (self.loop_mode, backward) {
+            (_, false) if index < end => (index + 1, false),
+            (_, true) if index > start => (index - 1, false),
+            (LoopMode::Loop, false) => (start, false),
+            (LoopMode::Loop, true) => (end, false),
+            (LoopMode::Once, _) => (index, false),
+            (LoopMode::PingPong, false) => (index.saturating_sub(1).max(start), true),
+            (LoopMode::PingPong, true) => ((index + 1).min(end), true),
+        }
+    }
+
+    /// Enables or disables following the flow actions of the current sprite (`GoTo`,
+    /// `GoToRandom`, `GoToIfPrevious`, `GoToStatic`, `End` and `Delete`) when their frame is
+    /// passed. Random choices are drawn from a generator seeded with [`AnimationPlayer::set_seed`].
+    #[inline]
+    pub fn set_follow_actions(&mut self, follow: bool) {
+        self.follow_actions = follow;
+    }
+
+    /// Reseeds the random generator and forgets the history of previous sprites, so that the
+    /// sequence of sprites played from now on only depends on the seed.
+    #[inline]
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = SplitMix64::new(seed);
+        self.history.clear();
+    }
+
+    /// IDs of the sprites played before the current one, oldest first.
+    #[inline]
+    pub fn history(&self) -> &[i16] {
+        &self.history
+    }
+
+    /// Whether playback was stopped by an `End` or `Delete` action.
+    #[inline]
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    fn resolve_jump(&mut self, action: &Action) -> Option<Jump> {
+        match action {
+            Action::GoTo(name, percent) => {
+                let skipped = percent.is_some_and(|percent| self.rng.below(100) >= percent.into());
+                if skipped {
+                    return None;
+                }
+                self.find_sprite(name).map(Jump::Sprite)
+            }
+            Action::GoToStatic => self.find_sprite(STATIC_ANIMATION).map(Jump::Sprite),
+            Action::GoToRandom(names, percents) => {
+                // optimized actions start with a marker, followed by names and their weights
+                let names = if percents.is_empty() {
+                    &names[..]
+                } else {
+                    names.get(1..).unwrap_or_default()
+                };
+                if names.is_empty() {
+                    return None;
+                }
+                let index = if percents.is_empty() {
+                    self.rng.below(names.len() as u32) as usize
+                } else {
+                    let total = percents.iter().map(|&percent| u32::from(percent)).sum();
+                    let mut roll = self.rng.below(total);
+                    percents
+                        .iter()
+                        .position(|&percent| {
+                            let hit = roll < u32::from(percent);
+                            roll = roll.saturating_sub(percent.into());
+                            hit
+                        })
+                        .unwrap_or(0)
+                };
+                self.find_sprite(names.get(index)?).map(Jump::Sprite)
+            }
+            Action::GoToIfPrevious(previous, next, default) => {
+                let last = self
+                    .history
+                    .last()
+                    .and_then(|id| self.animation.sprites.get(id));
+                let last_name = last.and_then(|sprite| sprite.name.name.as_deref());
+                let target = last_name
+                    .and_then(|last| {
+                        let index = previous
+                            .iter()
+                            .position(|name| same_animation(last, name))?;
+                        next.get(index)
+                    })
+                    .or(default.as_ref())?;
+                self.find_sprite(target).map(Jump::Sprite)
+            }
+            Action::End | Action::Delete => Some(Jump::Stop),
+            Action::AddParticle(..)
+            | Action::Hit
+            | Action::RunScript(_)
+            | Action::SetRadius(_)
+            | Action::Unknown { .. } => None,
+        }
+    }
+
+    /// Finds a sprite by its full name, or by its name without a direction prefix, preferring
+    /// the direction of the current sprite.
+    fn find_sprite(&self, name: &str) -> Option<i16> {
+        // sprites are iterated in a random order, so matches are taken by ascending ID to keep
+        // seeded playback reproducible
+        let mut sprites = self
+            .animation
+            .sprites
+            .values()
+            .filter_map(|sprite| Some((sprite.id, sprite.name.name.as_deref()?)))
+            .collect::<Vec<_>>();
+        sprites.sort_unstable_by_key(|&(id, _)| id);
+        let named = || sprites.iter().copied();
+        if let Some((id, _)) = named().find(|(_, sprite)| *sprite == name) {
+            return Some(id);
+        }
+        let current = self
+            .current_sprite()
+            .name
+            .name
+            .as_deref()
+            .unwrap_or_default();
+        let direction = current.split_once('_').map(|(direction, _)| direction);
+        let prefixed = direction.map(|direction| format!("{}_{}", direction, name));
+        named()
+            .find(|(_, sprite)| Some(*sprite) == prefixed.as_deref())
+            .or_else(|| named().find(|(_, sprite)| same_animation(sprite, name)))
+            .map(|(id, _)| id)
+    }
+
+    fn jump_to(&mut self, sprite: i16) {
+        self.push_history();
+        self.current_sprite = sprite;
+        self.frame = 0;
+        self.range = None;
+    }
+
+    fn push_history(&mut self) {
+        if self.history.len() == HISTORY_LEN {
+            self.history.remove(0);
+        }
+        self.history.push(self.current_sprite);
+    }
+
+    /// Enables or disables queueing of [`FrameEvent`]s. Recorded events should be drained
+    /// regularly with [`AnimationPlayer::drain_events`].
+    #[inline]
+    pub fn set_record_events(&mut self, record: bool) {
+        self.record_events = record;
+        if !record {
+            self.events.clear();
+        }
+    }
+
+    /// Takes the events queued since the last call, oldest first.
+    #[inline]
+    pub fn drain_events(&mut self) -> impl Iterator<Item = FrameEvent> + '_ {
+        self.events.drain(..)
+    }
+
+    /// Renders the current sprite part of the way to the frame playback moves to next,
+    /// with `progress` going from 0 to 1, without advancing playback.
+    pub fn render_tweened(&mut self, initial: SpriteTransform, progress: f32)
+    where
+        R: Render,
+    {
+        let tween = Tween {
+            frame: self.frame,
+            target: self.next_frame(self.reverse).0,
+            progress,
+        };
+        self.render_layered(initial, tween);
+    }
+
+    fn render_layered(&mut self, initial: SpriteTransform, tween: Tween)
     where
         R: Render,
     {
         let sprite = self.animation.sprites.get(&self.current_sprite).unwrap();
-        self.backend
-            .render_sprite(&self.animation, sprite, initial, self.frame);
-        self.frame += 1;
+        // built from the fields, as the backend is borrowed mutably at the same time
+        let layers = Layers {
+            highlight: self.highlight.as_ref(),
+            overrides: &self.overrides,
+            colors: &self.colors,
+            atlas: 0,
+        };
+        layers.render(&mut self.backend, &self.animation, sprite, initial, tween);
+    }
+
+    /// Everything drawn on top of the animation or in place of some of its parts.
+    #[inline]
+    fn layers(&self, atlas: usize) -> Layers<'_> {
+        Layers {
+            highlight: self.highlight.as_ref(),
+            overrides: &self.overrides,
+            colors: &self.colors,
+            atlas,
+        }
+    }
+
+    /// Sets how the animation is highlighted when rendering, if at all.
+    #[inline]
+    pub fn set_highlight(&mut self, highlight: Option<Highlight>) {
+        self.highlight = highlight;
+    }
+
+    #[inline]
+    pub fn highlight(&self) -> Option<&Highlight> {
+        self.highlight.as_ref()
+    }
+
+    /// Parts of the animation replaced by parts of other animations when rendering.
+    #[inline]
+    pub fn overrides(&self) -> &PartOverrides {
+        &self.overrides
+    }
+
+    #[inline]
+    pub fn overrides_mut(&mut self) -> &mut PartOverrides {
+        &mut self.overrides
+    }
+
+    /// Colors applied to parts of the animation when rendering.
+    #[inline]
+    pub fn colors(&self) -> &ColorSlots {
+        &self.colors
+    }
+
+    #[inline]
+    pub fn colors_mut(&mut self) -> &mut ColorSlots {
+        &mut self.colors
     }
 
+    /// Switches to another sprite, recording the current one in the [`history`](Self::history)
+    /// like a jump would, so that `GoToIfPrevious` actions see manual switches too.
     #[inline]
     pub fn set_sprite(&mut self, sprite: i16) {
+        self.push_history();
         self.current_sprite = sprite;
         self.frame = 0;
+        self.stopped = false;
+        self.range = None;
     }
 
     #[inline]
     pub fn set_frame(&mut self, frame: u32) {
         self.frame = frame;
+        self.stopped = false;
+    }
+
+    #[inline]
+    pub fn frame(&self) -> u32 {
+        self.frame
     }
 
     #[inline]
@@ -58,6 +513,13 @@ impl<R> AnimationPlayer<R> {
         self.animation.clone()
     }
 
+    /// Replaces the animation while keeping playback state, e.g. with one holding more sprites
+    /// of the same file. The current sprite must be part of it.
+    #[inline]
+    pub fn set_animation(&mut self, animation: Arc<Animation>) {
+        self.animation = animation;
+    }
+
     #[inline]
     pub fn current_sprite(&self) -> &Sprite {
         self.animation.sprites.get(&self.current_sprite).unwrap()
@@ -68,3 +530,320 @@ impl<R> AnimationPlayer<R> {
         self.current_sprite
     }
 }
+
+/// The highlight, overrides and colors of a player, stacked when rendering.
+///
+/// Overrides drive the traversal of the animation, while the silhouettes of the highlight and
+/// the colors only alter the shapes and sprites they are given, so they can be nested below.
+#[derive(Debug, Clone, Copy)]
+struct Layers<'a> {
+    highlight: Option<&'a Highlight>,
+    overrides: &'a PartOverrides,
+    colors: &'a ColorSlots,
+    atlas: usize,
+}
+
+impl Layers<'_> {
+    fn render<B: Render>(
+        &self,
+        backend: &mut B,
+        animation: &Animation,
+        sprite: &Sprite,
+        transform: SpriteTransform,
+        tween: Tween,
+    ) {
+        let mut backend = Recolored::new(backend, self.colors);
+        let Some(highlight) = self.highlight else {
+            self.render_overridden(&mut backend, animation, sprite, transform, tween);
+            return;
+        };
+        let color = highlight.color(animation);
+        let part = highlight.part.filter(|&part| part != sprite.id);
+        match highlight.mode {
+            HighlightMode::Outline => {
+                // the silhouettes are drawn behind the animation, shifted in eight directions
+                for i in 0..8 {
+                    let angle = i as f32 * std::f32::consts::FRAC_PI_4;
+                    let (dy, dx) = angle.sin_cos();
+                    let offset = transform.clone().combine(&SpriteTransform::translate(
+                        dx * highlight.width,
+                        dy * highlight.width,
+                    ));
+                    let mut silhouette = Silhouette::new(&mut backend, &color, 1., part);
+                    self.render_overridden(&mut silhouette, animation, sprite, offset, tween);
+                }
+                self.render_overridden(&mut backend, animation, sprite, transform, tween);
+            }
+            HighlightMode::Overlay => {
+                self.render_overridden(&mut backend, animation, sprite, transform.clone(), tween);
+                let mut silhouette =
+                    Silhouette::new(&mut backend, &color, highlight.strength, part);
+                self.render_overridden(&mut silhouette, animation, sprite, transform, tween);
+            }
+        }
+    }
+
+    #[inline]
+    fn render_overridden<B: Render>(
+        &self,
+        backend: &mut B,
+        animation: &Animation,
+        sprite: &Sprite,
+        transform: SpriteTransform,
+        tween: Tween,
+    ) {
+        Overridden::with_atlas(backend, self.overrides, self.atlas)
+            .render_sprite_tweened(animation, sprite, transform, tween);
+    }
+}
+
+/// What a flow action asks the player to do next.
+#[derive(Debug)]
+enum Jump {
+    Sprite(i16),
+    Stop,
+}
+
+/// Compares animation names ignoring their direction prefix, e.g. `1_AnimHit` and `AnimHit`.
+#[inline]
+fn same_animation(left: &str, right: &str) -> bool {
+    strip_direction(left) == strip_direction(right)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::overrides::{PartKey, Substitute};
+    use crate::render::ColorTransform;
+    use crate::types::{Color, Shape};
+
+    /// Records the shapes drawn, with the atlas they are drawn from and their color.
+    #[derive(Debug, Default)]
+    struct Recorder {
+        atlas: usize,
+        shapes: Vec<(i16, usize, Color)>,
+    }
+
+    impl Render for Recorder {
+        fn render(&mut self, shape: &Shape, transform: SpriteTransform) {
+            let color = transform.color.fold(Color::new(1., 1., 1., 1.));
+            self.shapes.push((shape.id, self.atlas, color));
+        }
+
+        fn set_atlas(&mut self, atlas: usize) {
+            self.atlas = atlas;
+        }
+    }
+
+    fn player() -> AnimationPlayer<()> {
+        let mut player = AnimationPlayer::new((), Arc::new(fixtures::animation()));
+        player.set_sprite(fixtures::ROOT);
+        player
+    }
+
+    fn played_frames(player: &mut AnimationPlayer<()>, count: usize) -> Vec<u32> {
+        (0..count)
+            .map(|_| {
+                player.advance(1);
+                player.frame_index()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keeps_counting_frames_while_looping() {
+        let mut player = player();
+        player.advance(4);
+
+        assert_eq!(player.frame(), 4);
+        assert_eq!(player.frame_index(), 1);
+    }
+
+    #[test]
+    fn loops_within_the_range() {
+        let mut player = player();
+        player.set_range(Some((2, 1)));
+
+        assert_eq!(player.frame_index(), 1);
+        assert_eq!(played_frames(&mut player, 4), [2, 1, 2, 1]);
+    }
+
+    #[test]
+    fn stays_on_the_last_frame_once() {
+        let mut player = player();
+        player.set_loop_mode(LoopMode::Once);
+
+        assert_eq!(played_frames(&mut player, 4), [1, 2, 2, 2]);
+        player.set_reverse(true);
+        assert_eq!(played_frames(&mut player, 3), [1, 0, 0]);
+    }
+
+    #[test]
+    fn turns_around_in_ping_pong() {
+        let mut player = player();
+        player.set_loop_mode(LoopMode::PingPong);
+
+        assert_eq!(played_frames(&mut player, 6), [1, 2, 1, 0, 1, 2]);
+        assert!(!player.is_reverse());
+        player.advance(1);
+        assert!(player.is_reverse());
+    }
+
+    #[test]
+    fn loops_backward_in_reverse() {
+        let mut player = player();
+        player.set_reverse(true);
+
+        assert_eq!(played_frames(&mut player, 4), [2, 1, 0, 2]);
+        player.step_forward();
+        assert_eq!(player.frame_index(), 0);
+    }
+
+    #[test]
+    fn ignores_random_jumps_without_names() {
+        let mut player = player();
+        let empty = Action::GoToRandom(vec![], vec![50]);
+        let marker_only = Action::GoToRandom(vec!["#optimized".to_owned()], vec![50]);
+
+        assert!(player.resolve_jump(&empty).is_none());
+        assert!(player.resolve_jump(&marker_only).is_none());
+        assert!(
+            player
+                .resolve_jump(&Action::GoToRandom(vec![], vec![]))
+                .is_none()
+        );
+    }
+
+    /// A player on the root sprite of an animation with walk cycles in several directions,
+    /// added in the given order.
+    fn player_with_directions(directions: &[i16]) -> AnimationPlayer<()> {
+        let mut animation = fixtures::animation();
+        let walk = animation.sprites[&fixtures::WALK].clone();
+        for &direction in directions {
+            let mut sprite = walk.clone();
+            sprite.id = 30 + direction;
+            sprite.name.name = Some(format!("{}_AnimMarche", direction));
+            animation.sprites.insert(sprite.id, sprite);
+        }
+        animation.sprites.remove(&fixtures::WALK);
+        let mut player = AnimationPlayer::new((), Arc::new(animation));
+        player.set_sprite(fixtures::ROOT);
+        player
+    }
+
+    #[test]
+    fn resolves_jumps_the_same_way_with_the_same_seed() {
+        let directions = [2, 3, 4, 5, 6, 7, 8, 9];
+        let mut reversed = directions;
+        reversed.reverse();
+        let action = Action::GoToRandom(
+            vec!["AnimMarche".to_owned(), "AnimStatique".to_owned()],
+            vec![],
+        );
+        let jumps = |player: &mut AnimationPlayer<()>| {
+            player.set_seed(7);
+            (0..16)
+                .map(|_| match player.resolve_jump(&action) {
+                    Some(Jump::Sprite(id)) => Some(id),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let first = jumps(&mut player_with_directions(&directions));
+        assert_eq!(first, jumps(&mut player_with_directions(&reversed)));
+        // the walk cycle without the direction of the root sprite with the lowest ID
+        assert!(first.contains(&Some(32)));
+        assert!(
+            first
+                .iter()
+                .all(|id| matches!(id, Some(32 | fixtures::ROOT)))
+        );
+    }
+
+    #[test]
+    fn records_and_drains_frame_events() {
+        let mut player = player();
+        player.advance(1);
+        assert_eq!(player.drain_events().count(), 0);
+
+        // the second frame plays again after looping, with the same actions
+        player.set_record_events(true);
+        player.advance(4);
+        let events = player
+            .drain_events()
+            .map(|event| (event.sprite, event.frame, event.action))
+            .collect::<Vec<_>>();
+        let go_to = |name: &str, percent| Action::GoTo(name.to_owned(), percent);
+        assert_eq!(
+            events,
+            [
+                (fixtures::ROOT, 1, go_to("1_AnimMarche", None)),
+                (fixtures::ROOT, 1, Action::GoToStatic),
+                (10, 1, Action::Hit),
+                (fixtures::ROOT, 2, go_to("1_AnimStatique", Some(50))),
+                (10, 0, Action::Hit),
+                (fixtures::ROOT, 4, go_to("1_AnimMarche", None)),
+                (fixtures::ROOT, 4, Action::GoToStatic),
+                (10, 1, Action::Hit),
+            ]
+        );
+        assert_eq!(player.drain_events().count(), 0);
+
+        // events still queued are dropped once recording stops
+        player.advance(1);
+        player.set_record_events(false);
+        player.advance(1);
+        assert_eq!(player.drain_events().count(), 0);
+    }
+
+    #[test]
+    fn records_manual_sprite_switches() {
+        let mut player = player();
+        player.set_sprite(fixtures::WALK);
+        assert_eq!(player.history().last(), Some(&fixtures::ROOT));
+
+        let action = Action::GoToIfPrevious(
+            vec!["AnimStatique".to_owned()],
+            vec!["AnimMarche".to_owned()],
+            None,
+        );
+        assert!(matches!(
+            player.resolve_jump(&action),
+            Some(Jump::Sprite(fixtures::WALK))
+        ));
+    }
+
+    #[test]
+    fn stacks_highlight_overrides_and_colors() {
+        let animation = Arc::new(fixtures::animation());
+        let mut player = AnimationPlayer::new(Recorder::default(), animation.clone());
+        player.set_sprite(fixtures::ROOT);
+        let source = player.overrides_mut().add_source(animation, 3);
+        player
+            .overrides_mut()
+            .set(PartKey::Id(11), Substitute { source, id: 12 });
+        player
+            .colors_mut()
+            .set(1000, ColorTransform::Multiply(0.5, 1., 1., 1.));
+        player.set_highlight(Some(Highlight {
+            mode: HighlightMode::Overlay,
+            part: Some(11),
+            ..Highlight::default()
+        }));
+        player.render(SpriteTransform::identity());
+
+        let shapes = &player.backend().shapes;
+        let drawn = shapes
+            .iter()
+            .map(|&(id, atlas, _)| (id, atlas))
+            .collect::<Vec<_>>();
+        // the recolored part, its overridden sibling, then the silhouette of the sibling
+        assert_eq!(drawn, [(1, 0), (1, 3), (2, 3), (1, 3), (2, 3)]);
+        assert_eq!(shapes[0].2.red, 0.5);
+        assert_eq!(shapes[1].2.red, 1.);
+        assert_eq!(shapes[3].2.green, 0.5);
+        assert_eq!(shapes[4].2.green, 0.5);
+    }
+}
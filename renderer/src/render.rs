@@ -1,11 +1,32 @@
 use euclid::default::{Box2D, Transform2D};
 
 use crate::frame_reader::FrameReader;
-use crate::types::{Animation, Color, Shape, Sprite, SpritePayload, TransformTable};
+use crate::timeline::{self, Tween};
+use crate::types::{Animation, Color, Shape, Sprite};
 
 pub trait Render {
     fn render(&mut self, shape: &Shape, transform: SpriteTransform);
 
+    /// Adjusts the transform a sprite is rendered with, before its children are placed.
+    fn sprite_transform(
+        &mut self,
+        _sprite: &Sprite,
+        transform: SpriteTransform,
+    ) -> SpriteTransform {
+        transform
+    }
+
+    /// Selects the atlas following shapes are drawn from. Atlas 0 is the one of the animation
+    /// being rendered, backends with several atlases register the others themselves.
+    fn set_atlas(&mut self, _atlas: usize) {}
+
+    /// Called before a sprite or shape placed by its parent is rendered, so that wrapping
+    /// backends can track where they are in the hierarchy.
+    fn enter(&mut self, _id: i16) {}
+
+    /// Called once a sprite or shape passed to [`enter`](Self::enter) is rendered.
+    fn leave(&mut self, _id: i16) {}
+
     fn render_sprite(
         &mut self,
         animation: &Animation,
@@ -13,33 +34,12 @@ pub trait Render {
         transform: SpriteTransform,
         frame: u32,
     ) {
-        let empty_table = &TransformTable::EMPTY;
-        let table = animation.transform.as_ref().unwrap_or(empty_table);
-        let mut reader = FrameReader::new(&sprite.frame_data, table);
-        match &sprite.payload {
-            SpritePayload::Single(sprite_id, _) | SpritePayload::SingleNoAction(sprite_id) => {
-                self.render_by_id(animation, *sprite_id, &transform, &mut reader, frame);
-            }
-            SpritePayload::SingleFrame(sprite_ids, _) => {
-                let transform = reader
-                    .read_transformation()
-                    .expect("transformation should be present")
-                    .combine(&transform);
-                for sprite_id in sprite_ids {
-                    self.render_at(animation, *sprite_id, transform.clone(), frame);
-                }
-            }
-            SpritePayload::Indexed(frame_pos, sprite_info, action_info) => {
-                let mult = if action_info.is_empty() { 2 } else { 3 };
-                let index = (frame as usize % sprite.frame_count()) * mult;
-                let offset = *frame_pos.get(index).unwrap() as usize;
-                let current = *frame_pos.get(index + 1).unwrap() as usize;
-                let count = *sprite_info.get(current).unwrap() as usize;
-                reader.seek(offset);
-                for sprite_id in sprite_info.iter().skip(current + 1).take(count) {
-                    self.render_by_id(animation, *sprite_id, &transform, &mut reader, frame);
-                }
-            }
+        let transform = self.sprite_transform(sprite, transform);
+        let placements = timeline::placements(animation, sprite, frame);
+        let frames = timeline::child_frames(animation, sprite, frame, &placements);
+        for (placement, frame) in placements.into_iter().zip(frames) {
+            let transform = placement.transform.combine(&transform);
+            self.render_at(animation, placement.id, transform, frame);
         }
     }
 
@@ -50,11 +50,13 @@ pub trait Render {
         transform: SpriteTransform,
         frame: u32,
     ) {
+        self.enter(id);
         if let Some(sprite) = anm.sprites.get(&id) {
             self.render_sprite(anm, sprite, transform, frame);
         } else if let Some(shape) = anm.shapes.get(&id) {
             self.render(shape, transform);
         }
+        self.leave(id);
     }
 
     fn render_by_id(
@@ -71,9 +73,43 @@ pub trait Render {
             .combine(parent);
         self.render_at(anm, id, transform, frame);
     }
+
+    /// Renders a sprite part of the way between two frames, interpolating its children.
+    fn render_sprite_tweened(
+        &mut self,
+        animation: &Animation,
+        sprite: &Sprite,
+        transform: SpriteTransform,
+        tween: Tween,
+    ) {
+        let transform = self.sprite_transform(sprite, transform);
+        let placements = timeline::placements(animation, sprite, tween.frame);
+        let tweens = timeline::child_tweens(animation, sprite, tween, &placements);
+        let tweened = timeline::tweened_placements(animation, sprite, tween);
+        for (placement, tween) in tweened.into_iter().zip(tweens) {
+            let transform = placement.transform.combine(&transform);
+            self.render_at_tweened(animation, placement.id, transform, tween);
+        }
+    }
+
+    fn render_at_tweened(
+        &mut self,
+        anm: &Animation,
+        id: i16,
+        transform: SpriteTransform,
+        tween: Tween,
+    ) {
+        self.enter(id);
+        if let Some(sprite) = anm.sprites.get(&id) {
+            self.render_sprite_tweened(anm, sprite, transform, tween);
+        } else if let Some(shape) = anm.shapes.get(&id) {
+            self.render(shape, transform);
+        }
+        self.leave(id);
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct SpriteTransform {
     pub position: Transform2D<f32>,
     pub color: ColorTransform,
@@ -96,6 +132,16 @@ impl SpriteTransform {
         }
     }
 
+    /// Blends two transforms component-wise, `t` going from 0 (`self`) to 1 (`other`).
+    pub fn lerp(&self, other: &SpriteTransform, t: f32) -> SpriteTransform {
+        let from = self.position.to_array();
+        let to = other.position.to_array();
+        SpriteTransform {
+            position: Transform2D::from_array(std::array::from_fn(|i| lerp(from[i], to[i], t))),
+            color: self.color.lerp(&other.color, t),
+        }
+    }
+
     #[inline]
     pub fn translate(x: f32, y: f32) -> SpriteTransform {
         SpriteTransform {
@@ -137,7 +183,7 @@ impl SpriteTransform {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ColorTransform {
     Multiply(f32, f32, f32, f32),
     Add(f32, f32, f32, f32),
@@ -185,6 +231,40 @@ impl ColorTransform {
     pub fn into_color(self) -> Color {
         self.fold(Color::WHITE)
     }
+
+    /// Blends two color transforms, `t` going from 0 (`self`) to 1 (`other`).
+    pub fn lerp(&self, other: &ColorTransform, t: f32) -> ColorTransform {
+        if self == other {
+            return self.clone();
+        }
+        let (from_mul, from_add) = self.to_affine();
+        let (to_mul, to_add) = other.to_affine();
+        let mul = std::array::from_fn::<_, 4, _>(|i| lerp(from_mul[i], to_mul[i], t));
+        let add = std::array::from_fn::<_, 4, _>(|i| lerp(from_add[i], to_add[i], t));
+        ColorTransform::Multiply(mul[0], mul[1], mul[2], mul[3])
+            .combine(&ColorTransform::Add(add[0], add[1], add[2], add[3]))
+    }
+
+    /// Reduces the transform to a multiplication followed by an addition on every channel.
+    fn to_affine(&self) -> ([f32; 4], [f32; 4]) {
+        match self {
+            ColorTransform::Multiply(r, g, b, a) => ([*r, *g, *b, *a], [0.; 4]),
+            ColorTransform::Add(r, g, b, a) => ([1.; 4], [*r, *g, *b, *a]),
+            ColorTransform::Combine(l, r) => {
+                let (l_mul, l_add) = l.to_affine();
+                let (r_mul, r_add) = r.to_affine();
+                (
+                    std::array::from_fn(|i| l_mul[i] * r_mul[i]),
+                    std::array::from_fn(|i| l_add[i] * r_mul[i] + r_add[i]),
+                )
+            }
+        }
+    }
+}
+
+#[inline]
+fn lerp(from: f32, to: f32, t: f32) -> f32 {
+    from + (to - from) * t
 }
 
 #[derive(Debug, Default)]
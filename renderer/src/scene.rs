@@ -0,0 +1,262 @@
+//! Composition of several animated entities on an isometric map.
+//!
+//! Entities are placed on map cells and drawn back to front, so that the ones closer to the
+//! viewer overlap the others. All of them are rendered through a single [`Render`] backend,
+//! which lets entities from different animations share a frame.
+use std::cmp::Ordering;
+
+use crate::player::AnimationPlayer;
+use crate::render::{ColorTransform, Render, SpriteTransform};
+
+/// Width of a map cell in pixels.
+pub const CELL_WIDTH: f32 = 86.;
+/// Height of a map cell in pixels.
+pub const CELL_HEIGHT: f32 = 43.;
+/// Height in pixels of one unit of altitude.
+pub const ELEVATION_UNIT: f32 = 10.;
+
+/// A position on the map, in cells, with an altitude.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct WorldPosition {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl WorldPosition {
+    #[inline]
+    pub fn new(x: f32, y: f32, z: f32) -> WorldPosition {
+        WorldPosition { x, y, z }
+    }
+
+    /// Projects the position to screen space, relative to the origin of the map.
+    #[inline]
+    pub fn to_screen(self) -> (f32, f32) {
+        (
+            (self.x - self.y) * CELL_WIDTH / 2.,
+            (self.x + self.y) * CELL_HEIGHT / 2. - self.z * ELEVATION_UNIT,
+        )
+    }
+}
+
+/// An animation placed in a scene.
+#[derive(Debug)]
+pub struct SceneEntity {
+    /// Plays the animation, which is drawn with the highlight, overrides and colors of the
+    /// player.
+    pub player: AnimationPlayer<()>,
+    pub position: WorldPosition,
+    /// Transform applied to the entity before it is moved to its position, e.g. a scale or
+    /// a horizontal flip.
+    pub transform: SpriteTransform,
+    /// Color transform applied on top of the colors of the animation.
+    pub tint: ColorTransform,
+    /// Index of the atlas of the animation in the backend, see [`Render::set_atlas`].
+    pub atlas: usize,
+}
+
+impl SceneEntity {
+    #[inline]
+    pub fn new(player: AnimationPlayer<()>, position: WorldPosition) -> SceneEntity {
+        SceneEntity {
+            player,
+            position,
+            transform: SpriteTransform::identity(),
+            tint: ColorTransform::identity(),
+            atlas: 0,
+        }
+    }
+
+    /// Height of the current sprite taken from the animation extension, if it has one.
+    pub fn height(&self) -> Option<i8> {
+        let animation = self.player.animation();
+        let heights = animation
+            .index
+            .as_ref()?
+            .extension
+            .as_ref()?
+            .heights
+            .as_ref()?;
+        heights
+            .get(&self.player.current_sprite().name.name_crc)
+            .copied()
+    }
+
+    /// Key ordering entities from the back of the map to the front. Entities on the same row
+    /// are ordered by altitude, then by height, so that taller ones are drawn last.
+    fn depth(&self) -> (f32, f32, i8) {
+        (
+            self.position.x + self.position.y,
+            self.position.z,
+            self.height().unwrap_or(0),
+        )
+    }
+}
+
+/// Identifies an entity of a scene.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EntityId(usize);
+
+/// A set of entities rendered together with depth sorting.
+#[derive(Debug, Default)]
+pub struct Scene {
+    /// Entities with the number of entities added before them, slots of removed entities are
+    /// reused.
+    entities: Vec<Option<(u64, SceneEntity)>>,
+    added: u64,
+}
+
+impl Scene {
+    #[inline]
+    pub fn new() -> Scene {
+        Scene::default()
+    }
+
+    pub fn add(&mut self, entity: SceneEntity) -> EntityId {
+        let entity = (self.added, entity);
+        self.added += 1;
+        if let Some(index) = self.entities.iter().position(Option::is_none) {
+            self.entities[index] = Some(entity);
+            EntityId(index)
+        } else {
+            self.entities.push(Some(entity));
+            EntityId(self.entities.len() - 1)
+        }
+    }
+
+    #[inline]
+    pub fn remove(&mut self, id: EntityId) -> Option<SceneEntity> {
+        Some(self.entities.get_mut(id.0)?.take()?.1)
+    }
+
+    #[inline]
+    pub fn get(&self, id: EntityId) -> Option<&SceneEntity> {
+        Some(&self.entities.get(id.0)?.as_ref()?.1)
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, id: EntityId) -> Option<&mut SceneEntity> {
+        Some(&mut self.entities.get_mut(id.0)?.as_mut()?.1)
+    }
+
+    pub fn entities(&self) -> impl Iterator<Item = (EntityId, &SceneEntity)> {
+        self.entities
+            .iter()
+            .enumerate()
+            .filter_map(|(i, entity)| Some((EntityId(i), &entity.as_ref()?.1)))
+    }
+
+    /// Advances every entity by one frame.
+    pub fn tick(&mut self) {
+        for (_, entity) in self.entities.iter_mut().flatten() {
+            entity.player.tick();
+        }
+    }
+
+    /// Renders every entity back to front, with `view` mapping the map to the screen.
+    pub fn render<B: Render>(&self, backend: &mut B, view: &SpriteTransform) {
+        let mut entities = self.entities.iter().flatten().collect::<Vec<_>>();
+        // entities at the same depth are drawn in the order they were added in
+        entities.sort_by(|(a_added, a), (b_added, b)| {
+            a.depth()
+                .partial_cmp(&b.depth())
+                .unwrap_or(Ordering::Equal)
+                .then(a_added.cmp(b_added))
+        });
+
+        for (_, entity) in entities {
+            let (x, y) = entity.position.to_screen();
+            let tint = SpriteTransform {
+                color: entity.tint.clone(),
+                ..SpriteTransform::identity()
+            };
+            let transform = tint
+                .combine(&entity.transform)
+                .combine(&SpriteTransform::translate(x, y))
+                .combine(view);
+            backend.set_atlas(entity.atlas);
+            entity.player.render_with(backend, transform, entity.atlas);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::fixtures;
+    use crate::types::Shape;
+
+    /// Records the atlases of the entities in the order they are drawn.
+    #[derive(Debug, Default)]
+    struct Recorder {
+        atlases: Vec<usize>,
+    }
+
+    impl Render for Recorder {
+        fn render(&mut self, _shape: &Shape, _transform: SpriteTransform) {}
+
+        fn set_atlas(&mut self, atlas: usize) {
+            if self.atlases.last() != Some(&atlas) {
+                self.atlases.push(atlas);
+            }
+        }
+    }
+
+    fn entity(atlas: usize, x: f32, y: f32, z: f32) -> SceneEntity {
+        let mut player = AnimationPlayer::new((), Arc::new(fixtures::animation()));
+        player.set_sprite(fixtures::ROOT);
+        SceneEntity {
+            atlas,
+            ..SceneEntity::new(player, WorldPosition::new(x, y, z))
+        }
+    }
+
+    fn drawn(scene: &Scene) -> Vec<usize> {
+        let mut recorder = Recorder::default();
+        scene.render(&mut recorder, &SpriteTransform::identity());
+        recorder.atlases
+    }
+
+    #[test]
+    fn reuses_the_slots_of_removed_entities() {
+        let mut scene = Scene::new();
+        let first = scene.add(entity(1, 0., 0., 0.));
+        let second = scene.add(entity(2, 0., 0., 0.));
+
+        assert_eq!(scene.remove(first).map(|entity| entity.atlas), Some(1));
+        assert!(scene.get(first).is_none());
+        assert!(scene.remove(first).is_none());
+
+        let third = scene.add(entity(3, 0., 0., 0.));
+        assert_eq!(third, first);
+        let atlases = scene
+            .entities()
+            .map(|(id, entity)| (id, entity.atlas))
+            .collect::<Vec<_>>();
+        assert_eq!(atlases, [(third, 3), (second, 2)]);
+    }
+
+    #[test]
+    fn draws_entities_back_to_front() {
+        let mut scene = Scene::new();
+        scene.add(entity(1, 2., 1., 0.));
+        scene.add(entity(2, 0., 0., 0.));
+        scene.add(entity(3, 1., 2., -1.));
+        scene.add(entity(4, 1., 0., 0.));
+
+        assert_eq!(drawn(&scene), [2, 4, 3, 1]);
+    }
+
+    #[test]
+    fn draws_entities_at_the_same_depth_in_the_order_they_were_added() {
+        let mut scene = Scene::new();
+        let first = scene.add(entity(1, 0., 0., 0.));
+        scene.add(entity(2, 0., 0., 0.));
+        scene.remove(first);
+        scene.add(entity(3, 0., 0., 0.));
+
+        assert_eq!(drawn(&scene), [2, 3]);
+    }
+}
@@ -0,0 +1,354 @@
+//! Access to collections of animations and their atlases.
+//!
+//! The game ships animations in `.jar` archives, storing each animation as `{name}.anm` and
+//! its atlas as `Atlas/{name}.png`. [`AnimationSource`] abstracts over where these files come
+//! from, so that archives, extracted directories and files held in memory are used the same way.
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use hashbrown::HashMap;
+use image::RgbaImage;
+use zip::ZipArchive;
+use zip::result::ZipError;
+
+use crate::decode::{DecodeOptions, Decoder};
+use crate::lazy::LazyAnimation;
+use crate::overrides::{PartKey, PartOverrides, Substitute};
+use crate::types::{Animation, Import};
+
+/// Path of an animation within a source.
+#[inline]
+pub fn animation_path(name: &str) -> String {
+    format!("{}.anm", name)
+}
+
+/// Path of an atlas within a source.
+#[inline]
+pub fn atlas_path(name: &str) -> String {
+    format!("Atlas/{}.png", name)
+}
+
+pub trait AnimationSource {
+    /// Reads a file by its path, using `/` as separator. Missing files are reported as
+    /// [`io::ErrorKind::NotFound`].
+    fn read_file(&mut self, path: &str) -> io::Result<Vec<u8>>;
+
+    /// Lists the paths of all the files in the source.
+    fn file_names(&mut self) -> io::Result<Vec<String>>;
+
+    /// Lists the names of the animations in the source, sorted.
+    fn animation_names(&mut self) -> io::Result<Vec<String>> {
+        let mut names = self
+            .file_names()?
+            .into_iter()
+            .filter(|path| !path.contains('/'))
+            .filter_map(|path| path.strip_suffix(".anm").map(str::to_owned))
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        Ok(names)
+    }
+
+    fn load_animation(&mut self, name: &str, options: DecodeOptions) -> io::Result<Animation> {
+        let data = self.read_file(&animation_path(name))?;
+        Decoder::new(options).decode_animation(&mut io::Cursor::new(data))
+    }
+
+    fn load_lazy_animation(
+        &mut self,
+        name: &str,
+        options: DecodeOptions,
+    ) -> io::Result<LazyAnimation> {
+        LazyAnimation::new(self.read_file(&animation_path(name))?, options)
+    }
+
+    fn load_atlas(&mut self, name: &str) -> io::Result<RgbaImage> {
+        let data = self.read_file(&atlas_path(name))?;
+        let image = image::load_from_memory_with_format(&data, image::ImageFormat::Png)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        Ok(image.to_rgba8())
+    }
+
+    /// Loads the atlas referenced by the texture of an animation.
+    fn load_animation_atlas(&mut self, animation: &Animation) -> io::Result<RgbaImage> {
+        let texture = animation.texture.as_ref().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "Animation has no texture")
+        })?;
+        self.load_atlas(&texture.name)
+    }
+
+    /// Loads the animations imported by `animation`, along with the sprite each import refers
+    /// to.
+    fn resolve_imports(
+        &mut self,
+        animation: &Animation,
+        options: DecodeOptions,
+    ) -> io::Result<Vec<ResolvedImport>> {
+        let mut loaded: HashMap<&str, Arc<Animation>> = HashMap::new();
+        let mut resolved = Vec::with_capacity(animation.imports.len());
+        for import in &animation.imports {
+            let name = import.name.strip_suffix(".anm").unwrap_or(&import.name);
+            let imported = if let Some(imported) = loaded.get(name) {
+                imported.clone()
+            } else {
+                let imported = Arc::new(self.load_animation(name, options)?);
+                loaded.insert(name, imported.clone());
+                imported
+            };
+            let sprite = imported
+                .sprites
+                .values()
+                .find(|sprite| sprite.name.name_crc == import.crc)
+                .map(|sprite| sprite.id)
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Import {} has no sprite in {}", import.id, name),
+                    )
+                })?;
+            resolved.push(ResolvedImport {
+                import: import.clone(),
+                animation: imported,
+                sprite,
+            });
+        }
+        Ok(resolved)
+    }
+}
+
+/// An import along with the animation it was loaded from.
+#[derive(Debug, Clone)]
+pub struct ResolvedImport {
+    pub import: Import,
+    pub animation: Arc<Animation>,
+    /// The sprite of `animation` the import refers to.
+    pub sprite: i16,
+}
+
+impl ResolvedImport {
+    /// Substitutes the import with the sprite it refers to, drawn from the given atlas.
+    pub fn apply(&self, overrides: &mut PartOverrides, atlas: usize) {
+        let source = overrides.add_source(self.animation.clone(), atlas);
+        overrides.set(
+            PartKey::Id(self.import.id),
+            Substitute {
+                source,
+                id: self.sprite,
+            },
+        );
+    }
+}
+
+/// Animations stored in a zip archive, like the `.jar` files of the game.
+#[derive(Debug)]
+pub struct JarSource<R> {
+    archive: ZipArchive<R>,
+}
+
+impl JarSource<File> {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<JarSource<File>> {
+        JarSource::new(File::open(path)?)
+    }
+}
+
+impl<R: io::Read + io::Seek> JarSource<R> {
+    pub fn new(reader: R) -> io::Result<JarSource<R>> {
+        let archive = ZipArchive::new(reader)?;
+        Ok(JarSource { archive })
+    }
+}
+
+impl<R: io::Read + io::Seek> AnimationSource for JarSource<R> {
+    fn read_file(&mut self, path: &str) -> io::Result<Vec<u8>> {
+        let mut entry = self.archive.by_name(path).map_err(|err| match err {
+            ZipError::FileNotFound => {
+                io::Error::new(io::ErrorKind::NotFound, format!("{} not found", path))
+            }
+            err => err.into(),
+        })?;
+        let mut buf = Vec::with_capacity(entry.size() as usize);
+        io::Read::read_to_end(&mut entry, &mut buf)?;
+        Ok(buf)
+    }
+
+    fn file_names(&mut self) -> io::Result<Vec<String>> {
+        Ok(self.archive.file_names().map(str::to_owned).collect())
+    }
+}
+
+/// Animations extracted to a directory.
+#[derive(Debug, Clone)]
+pub struct DirectorySource {
+    root: PathBuf,
+}
+
+impl DirectorySource {
+    #[inline]
+    pub fn new(root: impl Into<PathBuf>) -> DirectorySource {
+        DirectorySource { root: root.into() }
+    }
+
+    #[inline]
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+}
+
+impl AnimationSource for DirectorySource {
+    fn read_file(&mut self, path: &str) -> io::Result<Vec<u8>> {
+        std::fs::read(self.root.join(path))
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path, err)))
+    }
+
+    fn file_names(&mut self) -> io::Result<Vec<String>> {
+        let mut names = vec![];
+        list_files(&self.root, "", &mut names)?;
+        Ok(names)
+    }
+}
+
+fn list_files(dir: &Path, prefix: &str, names: &mut Vec<String>) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_dir() {
+            list_files(&entry.path(), &format!("{}{}/", prefix, name), names)?;
+        } else {
+            names.push(format!("{}{}", prefix, name));
+        }
+    }
+    Ok(())
+}
+
+/// Animations held in memory, keyed by their paths.
+#[derive(Debug, Clone, Default)]
+pub struct MemorySource {
+    files: HashMap<String, Vec<u8>>,
+}
+
+impl MemorySource {
+    #[inline]
+    pub fn new() -> MemorySource {
+        MemorySource::default()
+    }
+
+    #[inline]
+    pub fn insert(&mut self, path: impl Into<String>, data: Vec<u8>) {
+        self.files.insert(path.into(), data);
+    }
+
+    #[inline]
+    pub fn insert_animation(&mut self, name: &str, data: Vec<u8>) {
+        self.insert(animation_path(name), data);
+    }
+
+    #[inline]
+    pub fn insert_atlas(&mut self, name: &str, data: Vec<u8>) {
+        self.insert(atlas_path(name), data);
+    }
+
+    #[inline]
+    pub fn remove(&mut self, path: &str) -> Option<Vec<u8>> {
+        self.files.remove(path)
+    }
+}
+
+impl AnimationSource for MemorySource {
+    fn read_file(&mut self, path: &str) -> io::Result<Vec<u8>> {
+        self.files
+            .get(path)
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} not found", path)))
+    }
+
+    #[inline]
+    fn file_names(&mut self) -> io::Result<Vec<String>> {
+        Ok(self.files.keys().cloned().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode::EncodeExt;
+    use crate::fixtures;
+
+    fn encoded(animation: &Animation) -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.encode(animation).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn reads_files_held_in_memory() {
+        let mut source = MemorySource::new();
+        source.insert_animation("7001", encoded(&fixtures::animation()));
+        source.insert_animation("2", vec![]);
+        source.insert_atlas("7001", vec![1]);
+
+        assert_eq!(source.animation_names().unwrap(), ["2", "7001"]);
+        assert_eq!(source.read_file("Atlas/7001.png").unwrap(), [1]);
+        let animation = source
+            .load_animation("7001", DecodeOptions::default())
+            .unwrap();
+        assert_eq!(animation.sprites.len(), fixtures::animation().sprites.len());
+
+        let err = source.read_file("Atlas/2.png").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert_eq!(source.remove("Atlas/7001.png"), Some(vec![1]));
+        assert!(source.load_atlas("7001").is_err());
+    }
+
+    #[test]
+    fn resolves_imports_by_sprite_name() {
+        let mut animation = fixtures::animation();
+        animation.imports.push(Import {
+            id: 31,
+            name: "shared.anm".to_owned(),
+            crc: 9,
+        });
+        let mut shared = fixtures::animation();
+        shared
+            .sprites
+            .get_mut(&fixtures::WALK)
+            .unwrap()
+            .name
+            .name_crc = 9;
+        let mut source = MemorySource::new();
+        source.insert_animation("shared", encoded(&shared));
+
+        let resolved = source
+            .resolve_imports(&animation, DecodeOptions::default())
+            .unwrap();
+        assert_eq!(
+            resolved
+                .iter()
+                .map(|import| (import.import.id, import.sprite))
+                .collect::<Vec<_>>(),
+            [(30, fixtures::WALK), (31, fixtures::WALK)]
+        );
+        // both imports come from the same file, which is only loaded once
+        assert!(Arc::ptr_eq(&resolved[0].animation, &resolved[1].animation));
+
+        let mut overrides = PartOverrides::new();
+        resolved[0].apply(&mut overrides, 2);
+        let (source, id) = overrides.resolve(&animation, 30).unwrap();
+        assert_eq!((source.atlas, id), (2, fixtures::WALK));
+    }
+
+    #[test]
+    fn fails_to_resolve_missing_imports() {
+        let mut source = MemorySource::new();
+        let err = source
+            .resolve_imports(&fixtures::animation(), DecodeOptions::default())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        source.insert_animation("shared", encoded(&fixtures::animation()));
+        let err = source
+            .resolve_imports(&fixtures::animation(), DecodeOptions::default())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,417 @@
+//! Export of animations to the Spine skeletal JSON format.
+//!
+//! Every [`Instance`] in the hierarchy becomes a bone named after its path from the root,
+//! e.g. `root/12-0/7-1` for the second occurrence of `7` inside the first `12`. Shapes
+//! additionally get a slot with a region attachment named `shape_{id}`, and every named sprite
+//! becomes a Spine animation keyed on each of its frames. The atlas regions are written
+//! separately by [`to_atlas`] from the shape UVs.
+use euclid::default::Transform2D;
+use hashbrown::HashMap;
+use serde_json::{Map, Value, json};
+
+use crate::render::ColorTransform;
+use crate::timeline::{self, Instance};
+use crate::types::{Animation, Shape};
+
+const SPINE_VERSION: &str = "4.1";
+const ROOT: &str = "root";
+
+/// Builds a Spine skeleton with one animation per named sprite.
+pub fn to_json(animation: &Animation, images_path: &str) -> Value {
+    let mut bones = Hierarchy::default();
+    let mut animations = Map::new();
+
+    let mut sprites = animation
+        .sprites
+        .values()
+        .filter_map(|sprite| Some((sprite.name.name.as_deref()?, sprite)))
+        .collect::<Vec<_>>();
+    sprites.sort_unstable_by_key(|(name, _)| *name);
+
+    let frame_time = 1. / f64::from(animation.frame_rate.max(1));
+    let mut timelines = vec![];
+    for (name, sprite) in sprites {
+        let frames = (0..sprite.frame_count() as u32)
+            .map(|frame| {
+                let nodes = timeline::instances(animation, sprite, frame)
+                    .into_iter()
+                    .map(Node::new)
+                    .collect::<Vec<_>>();
+                bones.extend(&nodes);
+                nodes
+            })
+            .collect::<Vec<_>>();
+        timelines.push((name, frames));
+    }
+
+    for (name, frames) in timelines {
+        animations.insert(name.to_owned(), animation_json(&bones, &frames, frame_time));
+    }
+
+    let scale = animation.scale();
+    let mut bone_list = vec![json!({ "name": ROOT, "scaleX": scale, "scaleY": scale })];
+    bone_list.extend(bones.order.iter().map(|path| {
+        let parent = &bones.parents[path];
+        json!({ "name": path, "parent": parent })
+    }));
+
+    let slots = bones
+        .slots()
+        .map(|(path, _)| json!({ "name": path, "bone": path }))
+        .collect::<Vec<_>>();
+
+    let mut skin = Map::new();
+    for (path, shape_ids) in bones.slots() {
+        let mut attachments = Map::new();
+        for id in shape_ids {
+            if let Some(shape) = animation.shapes.get(id) {
+                attachments.insert(region_name(*id), region_json(shape));
+            }
+        }
+        skin.insert(path.clone(), Value::Object(attachments));
+    }
+
+    json!({
+        "skeleton": { "spine": SPINE_VERSION, "images": images_path },
+        "bones": bone_list,
+        "slots": slots,
+        "skins": [{ "name": "default", "attachments": skin }],
+        "animations": animations,
+    })
+}
+
+/// Builds a Spine atlas describing every shape as a region of the texture.
+pub fn to_atlas(animation: &Animation, image_name: &str, width: u32, height: u32) -> String {
+    let (w, h) = (width as f32, height as f32);
+    let mut out = format!(
+        "{}\nsize: {},{}\nformat: RGBA8888\nfilter: Linear,Linear\nrepeat: none\n",
+        image_name, width, height
+    );
+
+    let mut shapes = animation.shapes.values().collect::<Vec<_>>();
+    shapes.sort_unstable_by_key(|shape| shape.id);
+    for shape in shapes {
+        out.push_str(&format!(
+            "{}\n  bounds: {},{},{},{}\n",
+            region_name(shape.id),
+            (shape.left * w).round(),
+            (shape.top * h).round(),
+            ((shape.right - shape.left) * w).round(),
+            ((shape.bottom - shape.top) * h).round(),
+        ));
+    }
+    out
+}
+
+#[inline]
+fn region_name(id: i16) -> String {
+    format!("shape_{}", id)
+}
+
+fn region_json(shape: &Shape) -> Value {
+    let x = shape.offset_x + f32::from(shape.width) / 2.;
+    let y = shape.offset_y + f32::from(shape.height) / 2.;
+    // the atlas is stored upside down, matching `flip_y` in the renderer backends
+    json!({
+        "x": x,
+        "y": -y,
+        "scaleY": -1,
+        "width": shape.width,
+        "height": shape.height,
+    })
+}
+
+/// An instance with its bone name resolved.
+#[derive(Debug)]
+struct Node {
+    path: String,
+    parent: String,
+    instance: Instance,
+}
+
+impl Node {
+    fn new(instance: Instance) -> Node {
+        Node {
+            path: bone_name(&instance.path),
+            parent: bone_name(instance.parent()),
+            instance,
+        }
+    }
+}
+
+fn bone_name(path: &[(i16, usize)]) -> String {
+    path.iter()
+        .fold(ROOT.to_owned(), |mut name, (id, occurrence)| {
+            name.push_str(&format!("/{}-{}", id, occurrence));
+            name
+        })
+}
+
+/// All bones seen across the exported animations, parents first.
+#[derive(Debug, Default)]
+struct Hierarchy {
+    order: Vec<String>,
+    parents: HashMap<String, String>,
+    shapes: HashMap<String, Vec<i16>>,
+}
+
+impl Hierarchy {
+    fn extend(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            if !self.parents.contains_key(&node.path) {
+                self.order.push(node.path.clone());
+                self.parents.insert(node.path.clone(), node.parent.clone());
+            }
+            if let Some(shape) = node.instance.shape {
+                let shapes = self.shapes.entry(node.path.clone()).or_default();
+                if !shapes.contains(&shape) {
+                    shapes.push(shape);
+                }
+            }
+        }
+    }
+
+    fn slots(&self) -> impl Iterator<Item = (&String, &Vec<i16>)> {
+        self.order
+            .iter()
+            .filter_map(|path| Some((path, self.shapes.get(path)?)))
+    }
+}
+
+/// Keys are stepped, since ANM frames are not interpolated.
+fn animation_json(bones: &Hierarchy, frames: &[Vec<Node>], frame_time: f64) -> Value {
+    let mut bone_timelines = Map::new();
+    let mut slot_timelines = Map::new();
+
+    for path in &bones.order {
+        let mut translate = vec![];
+        let mut rotate = vec![];
+        let mut scale = vec![];
+        let mut shear = vec![];
+        let mut attachment = vec![];
+        let mut rgba = vec![];
+        let mut last_attachment = None;
+
+        for (frame, nodes) in frames.iter().enumerate() {
+            let time = frame as f64 * frame_time;
+            let node = nodes.iter().find(|node| &node.path == path);
+
+            if let Some(node) = node {
+                let pose = Pose::from_transform(&node.instance.transform);
+                let key = |mut value: Value| {
+                    value["time"] = json!(time);
+                    value["curve"] = json!("stepped");
+                    value
+                };
+                translate.push(key(json!({ "x": pose.x, "y": pose.y })));
+                rotate.push(key(json!({ "value": pose.rotation })));
+                scale.push(key(json!({ "x": pose.scale_x, "y": pose.scale_y })));
+                shear.push(key(json!({ "y": pose.shear_y })));
+                rgba.push(key(json!({ "color": hex_color(&node.instance.color) })));
+            }
+
+            let current = node.and_then(|node| node.instance.shape).map(region_name);
+            if frame == 0 || current != last_attachment {
+                attachment.push(json!({ "time": time, "name": current }));
+                last_attachment = current;
+            }
+        }
+
+        if !translate.is_empty() {
+            bone_timelines.insert(
+                path.clone(),
+                json!({
+                    "translate": translate,
+                    "rotate": rotate,
+                    "scale": scale,
+                    "shear": shear,
+                }),
+            );
+        }
+        if bones.shapes.contains_key(path) {
+            slot_timelines.insert(
+                path.clone(),
+                json!({ "attachment": attachment, "rgba": rgba }),
+            );
+        }
+    }
+
+    json!({ "bones": bone_timelines, "slots": slot_timelines })
+}
+
+/// A local affine transform decomposed into Spine bone properties, in a y-up space.
+#[derive(Debug)]
+struct Pose {
+    x: f32,
+    y: f32,
+    rotation: f32,
+    scale_x: f32,
+    scale_y: f32,
+    shear_y: f32,
+}
+
+impl Pose {
+    fn from_transform(transform: &Transform2D<f32>) -> Pose {
+        // conjugate with a vertical flip to go from the y-down screen space to Spine's y-up
+        let a = transform.m11;
+        let b = -transform.m21;
+        let c = -transform.m12;
+        let d = transform.m22;
+
+        let scale_x = a.hypot(c);
+        let mut scale_y = b.hypot(d);
+        let rotation = c.atan2(a).to_degrees();
+        let (b, d) = if a * d - b * c < 0. {
+            scale_y = -scale_y;
+            (-b, -d)
+        } else {
+            (b, d)
+        };
+        let shear_y = d.atan2(b).to_degrees() - 90. - rotation;
+
+        Pose {
+            x: transform.m31,
+            y: -transform.m32,
+            rotation,
+            scale_x,
+            scale_y,
+            shear_y: normalize_angle(shear_y),
+        }
+    }
+}
+
+fn normalize_angle(degrees: f32) -> f32 {
+    let degrees = degrees % 360.;
+    if degrees > 180. {
+        degrees - 360.
+    } else if degrees < -180. {
+        degrees + 360.
+    } else {
+        degrees
+    }
+}
+
+fn hex_color(color: &ColorTransform) -> String {
+    let color = color.clone().into_color();
+    let channel = |value: f32| (value.clamp(0., 1.) * 255.).round() as u8;
+    format!(
+        "{:02x}{:02x}{:02x}{:02x}",
+        channel(color.red),
+        channel(color.green),
+        channel(color.blue),
+        channel(color.alpha)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use euclid::Angle;
+
+    use super::*;
+    use crate::fixtures;
+
+    /// Rebuilds the y-up matrix of a pose, as Spine does for a bone without shear on x.
+    fn compose(pose: &Pose) -> [f32; 4] {
+        let rotation = pose.rotation.to_radians();
+        let y_axis = (pose.rotation + 90. + pose.shear_y).to_radians();
+        [
+            rotation.cos() * pose.scale_x,
+            y_axis.cos() * pose.scale_y,
+            rotation.sin() * pose.scale_x,
+            y_axis.sin() * pose.scale_y,
+        ]
+    }
+
+    fn assert_decomposes(transform: Transform2D<f32>) {
+        let pose = Pose::from_transform(&transform);
+        let expected = [transform.m11, -transform.m21, -transform.m12, transform.m22];
+        for (value, expected) in compose(&pose).into_iter().zip(expected) {
+            assert!(
+                (value - expected).abs() < 1e-4,
+                "{:?} decomposed as {:?}",
+                transform,
+                pose
+            );
+        }
+        assert_eq!((pose.x, pose.y), (transform.m31, -transform.m32));
+    }
+
+    #[test]
+    fn decomposes_translations_and_rotations() {
+        let pose = Pose::from_transform(&Transform2D::translation(3., 4.));
+        assert_eq!((pose.x, pose.y), (3., -4.));
+        assert_eq!((pose.scale_x, pose.scale_y), (1., 1.));
+        assert_eq!((pose.rotation, pose.shear_y), (0., 0.));
+
+        // clockwise on screen is counterclockwise in Spine
+        let pose = Pose::from_transform(&Transform2D::rotation(Angle::degrees(90.)));
+        assert!((pose.rotation + 90.).abs() < 1e-4);
+        assert!(pose.shear_y.abs() < 1e-4);
+    }
+
+    #[test]
+    fn decomposes_flips_into_a_negative_scale() {
+        let pose = Pose::from_transform(&Transform2D::scale(-1., 1.));
+        assert_eq!(
+            (pose.rotation.abs(), pose.scale_x, pose.scale_y),
+            (180., 1., -1.)
+        );
+        assert!(pose.shear_y.abs() < 1e-4);
+
+        assert_decomposes(Transform2D::scale(2., -3.).then_translate((5., -6.).into()));
+        assert_decomposes(Transform2D::scale(-0.5, 1.5).then_rotate(Angle::degrees(30.)));
+        assert_decomposes(Transform2D::new(1., 0.5, 0.25, -2., 7., 8.));
+    }
+
+    #[test]
+    fn builds_bones_slots_and_stepped_keys() {
+        let animation = fixtures::animation();
+        let json = to_json(&animation, "images/");
+
+        assert_eq!(json["skeleton"]["images"], "images/");
+        let scale = animation.scale();
+        assert_eq!(
+            json["bones"][0],
+            json!({ "name": "root", "scaleX": scale, "scaleY": scale })
+        );
+        let bones = json["bones"].as_array().unwrap();
+        let parent = |name: &str| {
+            bones
+                .iter()
+                .find(|bone| bone["name"] == name)
+                .map(|bone| bone["parent"].clone())
+        };
+        assert_eq!(parent("root/10-0"), Some(json!("root")));
+        assert_eq!(parent("root/10-0/1-0"), Some(json!("root/10-0")));
+
+        // only bones placing shapes get a slot, on themselves
+        let slots = json["slots"].as_array().unwrap();
+        assert!(slots.iter().all(|slot| slot["name"] == slot["bone"]));
+        assert!(slots.iter().any(|slot| slot["name"] == "root/10-0/1-0"));
+        assert!(!slots.iter().any(|slot| slot["name"] == "root/10-0"));
+        assert_eq!(
+            json["skins"][0]["attachments"]["root/1-0"]["shape_1"],
+            json!({ "x": 0., "y": 16., "scaleY": -1, "width": 16, "height": 32 })
+        );
+
+        let animations = json["animations"].as_object().unwrap();
+        assert_eq!(
+            animations.keys().collect::<Vec<_>>(),
+            ["1_AnimMarche", "1_AnimStatique", "1_Part"]
+        );
+        let walk = &animations["1_AnimMarche"];
+        let translate = walk["bones"]["root/1-0"]["translate"].as_array().unwrap();
+        assert_eq!(translate.len(), 2);
+        assert_eq!(translate[1]["time"], 1. / 30.);
+        assert_eq!(translate[1]["curve"], "stepped");
+        assert_eq!(
+            (&translate[1]["x"], &translate[1]["y"]),
+            (&json!(10.), &json!(5.))
+        );
+        // parts of other animations are hidden
+        assert_eq!(
+            walk["slots"]["root/10-0/1-0"]["attachment"],
+            json!([{ "time": 0., "name": null }])
+        );
+    }
+}
@@ -0,0 +1,109 @@
+//! Human-editable text representation of animations.
+//!
+//! An [`Animation`] can be written as [RON](https://github.com/ron-rs/ron) or JSON and read
+//! back without loss, so that it can be diffed, edited by hand and re-encoded to `.anm`.
+//! Both formats mirror the structures in [`crate::types`] field by field:
+//!
+//! - `shapes` and `sprites` are maps keyed by id, written in ascending id order,
+//! - `shape_order` and `sprite_order` list the ids in the order of the decoded file, which is
+//!   kept on encoding; ids missing from them are encoded after the others, by id,
+//! - shape UVs are fractions of the atlas size and are rounded back to 1/65535 on encoding,
+//! - flag wrappers (`version`, `flags`) are plain integers, and the optional fields they
+//!   control must be present exactly when the corresponding flag bit is set,
+//! - `frame_data` holds the raw transform stream, tagged `Bytes`, `Shorts` or `Ints`,
+//! - actions are tagged by variant name, e.g. `GoTo("static", None)` in RON.
+//!
+//! A shortened RON example:
+//!
+//! ```ron
+//! (
+//!     version: 25,
+//!     header: 0,
+//!     frame_rate: 30,
+//!     index: None,
+//!     texture: Some((name: "7001", crc: 1283714452)),
+//!     shapes: {
+//!         1: (id: 1, texture_index: 0, top: 0.0, left: 0.0, bottom: 0.25, right: 0.5, ...),
+//!     },
+//!     transform: Some((colors: [], rotations: [], translations: [0.0, 0.0], actions: [])),
+//!     sprites: { ... },
+//!     imports: [],
+//!     shape_order: [1],
+//!     sprite_order: [ ... ],
+//! )
+//! ```
+use std::io;
+
+use crate::decode::Decode;
+use crate::encode::Encode;
+use crate::types::Animation;
+
+/// Serializes an animation to pretty-printed RON.
+pub fn to_ron(animation: &Animation) -> io::Result<String> {
+    let config = ron::ser::PrettyConfig::default().indentor("  ");
+    ron::ser::to_string_pretty(animation, config).map_err(io::Error::other)
+}
+
+/// Parses an animation from RON.
+pub fn from_ron(text: &str) -> io::Result<Animation> {
+    ron::from_str(text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Serializes an animation to pretty-printed JSON.
+pub fn to_json(animation: &Animation) -> io::Result<String> {
+    serde_json::to_string_pretty(animation).map_err(io::Error::other)
+}
+
+/// Parses an animation from JSON.
+pub fn from_json(text: &str) -> io::Result<Animation> {
+    serde_json::from_str(text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Decodes a binary `.anm` and converts it to RON.
+pub fn anm_to_ron<R: io::Read>(input: &mut R) -> io::Result<String> {
+    to_ron(&Animation::decode(input)?)
+}
+
+/// Parses RON and encodes the result as a binary `.anm`.
+pub fn ron_to_anm<W: io::Write>(text: &str, output: &mut W) -> io::Result<()> {
+    from_ron(text)?.encode(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn anm() -> Vec<u8> {
+        let mut bytes = vec![];
+        fixtures::animation().encode(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trips_through_ron() {
+        let bytes = anm();
+        let text = anm_to_ron(&mut bytes.as_slice()).unwrap();
+        let mut encoded = vec![];
+        ron_to_anm(&text, &mut encoded).unwrap();
+        assert_eq!(encoded, bytes);
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let bytes = anm();
+        let animation = Animation::decode(&mut bytes.as_slice()).unwrap();
+        let text = to_json(&animation).unwrap();
+        let mut encoded = vec![];
+        from_json(&text).unwrap().encode(&mut encoded).unwrap();
+        assert_eq!(encoded, bytes);
+    }
+
+    #[test]
+    fn writes_maps_in_id_order() {
+        let text = to_ron(&fixtures::animation()).unwrap();
+        let positions = ["10: (", "11: (", "12: (", "20: (", "21: ("]
+            .map(|key| text.find(key).unwrap());
+        assert!(positions.is_sorted());
+    }
+}
@@ -0,0 +1,467 @@
+use euclid::default::Transform2D;
+use hashbrown::HashMap;
+
+use crate::frame_reader::FrameReader;
+use crate::render::{ColorTransform, SpriteTransform};
+use crate::types::{Action, Animation, Sprite, SpritePayload, TransformTable};
+
+/// A sprite or shape placed by its parent on a given frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placement {
+    /// ID of the placed sprite or shape.
+    pub id: i16,
+    /// Transform relative to the parent sprite.
+    pub transform: SpriteTransform,
+}
+
+/// Decodes the children placed by a sprite on a given frame, in drawing order.
+pub fn placements(animation: &Animation, sprite: &Sprite, frame: u32) -> Vec<Placement> {
+    let empty_table = &TransformTable::EMPTY;
+    let table = animation.transform.as_ref().unwrap_or(empty_table);
+    let mut reader = FrameReader::new(&sprite.frame_data, table);
+    match &sprite.payload {
+        SpritePayload::Single(sprite_id, _) | SpritePayload::SingleNoAction(sprite_id) => {
+            vec![Placement {
+                id: *sprite_id,
+                transform: read_transformation(&mut reader),
+            }]
+        }
+        SpritePayload::SingleFrame(sprite_ids, _) => {
+            let transform = read_transformation(&mut reader);
+            sprite_ids
+                .iter()
+                .map(|&id| Placement {
+                    id,
+                    transform: transform.clone(),
+                })
+                .collect()
+        }
+        SpritePayload::Indexed(frame_pos, sprite_info, action_info) => {
+            let mult = if action_info.is_empty() { 2 } else { 3 };
+            let index = (frame as usize % sprite.frame_count()) * mult;
+            let offset = *frame_pos.get(index).unwrap() as usize;
+            let current = *frame_pos.get(index + 1).unwrap() as usize;
+            let count = *sprite_info.get(current).unwrap() as usize;
+            reader.seek(offset);
+            sprite_info
+                .iter()
+                .skip(current + 1)
+                .take(count)
+                .map(|&id| Placement {
+                    id,
+                    transform: read_transformation(&mut reader),
+                })
+                .collect()
+        }
+    }
+}
+
+/// Lists the sprites and shapes a sprite places on any of its frames, in order of appearance.
+pub fn parts(animation: &Animation, sprite: &Sprite) -> Vec<i16> {
+    let mut parts = vec![];
+    for frame in 0..sprite.frame_count() as u32 {
+        for placement in placements(animation, sprite, frame) {
+            if !parts.contains(&placement.id) {
+                parts.push(placement.id);
+            }
+        }
+    }
+    parts
+}
+
+/// Computes the local frame of every child placed on a frame, as returned by [`placements`].
+///
+/// A child starts playing from its first frame when its parent places it, and keeps advancing
+/// for as long as every following frame of the parent places it again. Children are matched
+/// across frames by ID and occurrence. `frame` is the number of frames the parent has been
+/// playing for, so a child present on every frame of a looping parent is never restarted.
+pub fn child_frames(
+    animation: &Animation,
+    sprite: &Sprite,
+    frame: u32,
+    placements: &[Placement],
+) -> Vec<u32> {
+    let keys = occurrence_keys(placements);
+    let mut frames = vec![0; keys.len()];
+    let mut alive = vec![true; keys.len()];
+    let lookback = frame.min(sprite.frame_count() as u32);
+
+    for step in 1..=lookback {
+        let previous = occurrence_keys(&self::placements(animation, sprite, frame - step));
+        let mut any_alive = false;
+        for (i, key) in keys.iter().enumerate() {
+            if alive[i] && previous.contains(key) {
+                frames[i] += 1;
+                any_alive = true;
+            } else {
+                alive[i] = false;
+            }
+        }
+        if !any_alive {
+            return frames;
+        }
+    }
+    // children that were there for a whole loop have been playing since the parent started
+    if lookback < frame {
+        for (i, alive) in alive.into_iter().enumerate() {
+            if alive {
+                frames[i] = frame;
+            }
+        }
+    }
+    frames
+}
+
+fn occurrence_keys(placements: &[Placement]) -> Vec<(i16, usize)> {
+    let mut occurrences = HashMap::<i16, usize>::new();
+    placements
+        .iter()
+        .map(|placement| {
+            let occurrence = occurrences.entry(placement.id).or_default();
+            *occurrence += 1;
+            (placement.id, *occurrence - 1)
+        })
+        .collect()
+}
+
+/// A point between two frames of a sprite.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tween {
+    /// The frame blended from, counted like the `frame` of [`child_frames`].
+    pub frame: u32,
+    /// The frame blended into, usually the one playback moves to next.
+    pub target: u32,
+    /// How far along the way to `target`, from 0 to 1.
+    pub progress: f32,
+}
+
+impl Tween {
+    /// A tween from a frame to the one following it.
+    #[inline]
+    pub fn forward(frame: u32, progress: f32) -> Tween {
+        Tween {
+            frame,
+            target: frame.wrapping_add(1),
+            progress,
+        }
+    }
+}
+
+/// Decodes the children placed by a sprite part of the way between two frames. Children
+/// present on both frames have their transforms interpolated, matched by ID and occurrence,
+/// while the others are taken from the frame blended from.
+pub fn tweened_placements(animation: &Animation, sprite: &Sprite, tween: Tween) -> Vec<Placement> {
+    let current = placements(animation, sprite, tween.frame);
+    if tween.progress <= 0. || tween.frame == tween.target {
+        return current;
+    }
+
+    let mut next = placements(animation, sprite, tween.target)
+        .into_iter()
+        .map(Some)
+        .collect::<Vec<_>>();
+    current
+        .into_iter()
+        .map(|placement| {
+            // take the first unmatched placement with the same ID in the target frame
+            let target = next
+                .iter_mut()
+                .find(|other| other.as_ref().is_some_and(|other| other.id == placement.id))
+                .and_then(Option::take);
+            match target {
+                Some(target) => Placement {
+                    id: placement.id,
+                    transform: placement.transform.lerp(&target.transform, tween.progress),
+                },
+                None => placement,
+            }
+        })
+        .collect()
+}
+
+/// Computes how every child placed on the frame a tween starts from is tweened itself, as
+/// returned by [`child_frames`] on both frames. Children absent from the target frame stay on
+/// their frame.
+pub fn child_tweens(
+    animation: &Animation,
+    sprite: &Sprite,
+    tween: Tween,
+    placements: &[Placement],
+) -> Vec<Tween> {
+    let frames = child_frames(animation, sprite, tween.frame, placements);
+    let target_placements = self::placements(animation, sprite, tween.target);
+    let target_frames = child_frames(animation, sprite, tween.target, &target_placements);
+    let target_keys = occurrence_keys(&target_placements);
+    occurrence_keys(placements)
+        .into_iter()
+        .zip(frames)
+        .map(|(key, frame)| {
+            let target = target_keys
+                .iter()
+                .position(|other| *other == key)
+                .map_or(frame, |i| target_frames[i]);
+            Tween {
+                frame,
+                target,
+                progress: tween.progress,
+            }
+        })
+        .collect()
+}
+
+/// A sprite or shape placed somewhere in the hierarchy of a frame.
+#[derive(Debug, Clone)]
+pub struct Instance {
+    /// Path from the root, made of the ID of every ancestor and of the instance itself,
+    /// each paired with its occurrence among the siblings with the same ID.
+    pub path: Vec<(i16, usize)>,
+    /// Position transform relative to the parent instance.
+    pub transform: Transform2D<f32>,
+    /// Color transform accumulated from the root.
+    pub color: ColorTransform,
+    /// The shape drawn by this instance, if it is not a sprite.
+    pub shape: Option<i16>,
+}
+
+impl Instance {
+    /// The path of the parent instance.
+    #[inline]
+    pub fn parent(&self) -> &[(i16, usize)] {
+        &self.path[..self.path.len() - 1]
+    }
+}
+
+/// Flattens the hierarchy of a sprite on a given frame, parents before their children.
+pub fn instances(animation: &Animation, sprite: &Sprite, frame: u32) -> Vec<Instance> {
+    let mut out = vec![];
+    collect_instances(
+        animation,
+        sprite,
+        frame,
+        &[],
+        &ColorTransform::identity(),
+        &mut out,
+    );
+    out
+}
+
+fn collect_instances(
+    animation: &Animation,
+    sprite: &Sprite,
+    frame: u32,
+    parent: &[(i16, usize)],
+    parent_color: &ColorTransform,
+    out: &mut Vec<Instance>,
+) {
+    let placements = placements(animation, sprite, frame);
+    let frames = child_frames(animation, sprite, frame, &placements);
+    let keys = occurrence_keys(&placements);
+    for ((placement, child_frame), key) in placements.into_iter().zip(frames).zip(keys) {
+        let mut path = parent.to_vec();
+        path.push(key);
+
+        let color = placement.transform.color.combine(parent_color);
+        let child = animation.sprites.get(&placement.id);
+        if child.is_none() && !animation.shapes.contains_key(&placement.id) {
+            continue;
+        }
+        out.push(Instance {
+            path: path.clone(),
+            transform: placement.transform.position,
+            color: color.clone(),
+            shape: child.is_none().then_some(placement.id),
+        });
+        if let Some(child) = child {
+            collect_instances(animation, child, child_frame, &path, &color, out);
+        }
+    }
+}
+
+/// Resolves the actions attached to a sprite on a given frame.
+///
+/// Indexed sprites store, for every frame, an offset into the action info array where a count
+/// is followed by that many indices into the action table. Other sprites have a single frame
+/// and list the action indices directly.
+pub fn actions<'a>(animation: &'a Animation, sprite: &Sprite, frame: u32) -> Vec<&'a Action> {
+    let Some(table) = &animation.transform else {
+        return vec![];
+    };
+    let indices = match &sprite.payload {
+        SpritePayload::Single(_, action_info) | SpritePayload::SingleFrame(_, action_info) => {
+            action_info.as_slice()
+        }
+        SpritePayload::SingleNoAction(_) => &[],
+        SpritePayload::Indexed(_, _, action_info) if action_info.is_empty() => &[],
+        SpritePayload::Indexed(frame_pos, _, action_info) => {
+            let index = (frame as usize % sprite.frame_count()) * 3;
+            let Some(offset) = frame_pos
+                .get(index + 2)
+                .and_then(|&offset| usize::try_from(offset).ok())
+            else {
+                return vec![];
+            };
+            let count = action_info
+                .get(offset)
+                .and_then(|&count| usize::try_from(count).ok())
+                .unwrap_or(0);
+            action_info
+                .get(offset + 1..)
+                .map_or(&[][..], |rest| &rest[..count.min(rest.len())])
+        }
+    };
+    indices
+        .iter()
+        .filter_map(|&index| table.actions.get(usize::try_from(index).ok()?))
+        .collect()
+}
+
+/// An action attached to a sprite somewhere in the hierarchy of a frame.
+#[derive(Debug, Clone, Copy)]
+pub struct TriggeredAction<'a> {
+    /// ID of the sprite carrying the action.
+    pub sprite: i16,
+    /// Local frame of that sprite.
+    pub frame: u32,
+    pub action: &'a Action,
+}
+
+/// Resolves the actions of a sprite and of all its nested sprites on a given frame, parents
+/// first.
+pub fn triggered_actions<'a>(
+    animation: &'a Animation,
+    sprite: &Sprite,
+    frame: u32,
+) -> Vec<TriggeredAction<'a>> {
+    let mut out = actions(animation, sprite, frame)
+        .into_iter()
+        .map(|action| TriggeredAction {
+            sprite: sprite.id,
+            frame,
+            action,
+        })
+        .collect::<Vec<_>>();
+
+    let placements = placements(animation, sprite, frame);
+    let frames = child_frames(animation, sprite, frame, &placements);
+    for (placement, frame) in placements.into_iter().zip(frames) {
+        if let Some(child) = animation.sprites.get(&placement.id) {
+            out.extend(triggered_actions(animation, child, frame));
+        }
+    }
+    out
+}
+
+#[inline]
+fn read_transformation(reader: &mut FrameReader<'_>) -> SpriteTransform {
+    reader
+        .read_transformation()
+        .expect("transformation should be present")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn tween(frame: u32, target: u32, progress: f32) -> Tween {
+        Tween {
+            frame,
+            target,
+            progress,
+        }
+    }
+
+    fn blended(from: &[Placement], to: &[Placement], t: f32) -> Vec<Placement> {
+        from.iter()
+            .zip(to)
+            .map(|(from, to)| Placement {
+                id: from.id,
+                transform: from.transform.lerp(&to.transform, t),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn advances_children_while_they_stay_placed() {
+        let animation = fixtures::animation();
+        let root = &animation.sprites[&fixtures::ROOT];
+        let frames = |frame| {
+            let placements = placements(&animation, root, frame);
+            child_frames(&animation, root, frame, &placements)
+        };
+
+        assert_eq!(frames(0), [0, 0]);
+        assert_eq!(frames(1), [1, 1]);
+        assert_eq!(frames(2), [0]);
+        // the loop starts over after the last frame removed them
+        assert_eq!(frames(3), [0, 0]);
+        assert_eq!(frames(4), [1, 1]);
+    }
+
+    #[test]
+    fn keeps_the_phase_of_children_placed_on_every_frame() {
+        let animation = fixtures::animation();
+        let walk = &animation.sprites[&fixtures::WALK];
+        let placements = placements(&animation, walk, 5);
+
+        assert_eq!(child_frames(&animation, walk, 5, &placements), [5]);
+    }
+
+    #[test]
+    fn tweens_toward_the_target_frame() {
+        let animation = fixtures::animation();
+        let root = &animation.sprites[&fixtures::ROOT];
+        let first = placements(&animation, root, 0);
+        let second = placements(&animation, root, 1);
+        assert_ne!(first, second);
+
+        let forward = tweened_placements(&animation, root, tween(0, 1, 0.25));
+        assert_eq!(forward, blended(&first, &second, 0.25));
+        let backward = tweened_placements(&animation, root, tween(1, 0, 0.25));
+        assert_eq!(backward, blended(&second, &first, 0.25));
+    }
+
+    #[test]
+    fn holds_frames_without_a_different_target() {
+        let animation = fixtures::animation();
+        let root = &animation.sprites[&fixtures::ROOT];
+        let last = placements(&animation, root, 2);
+
+        assert_eq!(tweened_placements(&animation, root, tween(2, 2, 0.5)), last);
+        assert_eq!(tweened_placements(&animation, root, tween(2, 0, 0.)), last);
+    }
+
+    #[test]
+    fn keeps_children_missing_from_the_target() {
+        let animation = fixtures::animation();
+        let root = &animation.sprites[&fixtures::ROOT];
+
+        let tweened = tweened_placements(&animation, root, tween(1, 2, 0.5));
+        assert_eq!(tweened, placements(&animation, root, 1));
+    }
+
+    #[test]
+    fn tweens_children_along_with_their_parent() {
+        let animation = fixtures::animation();
+        let root = &animation.sprites[&fixtures::ROOT];
+        let second = placements(&animation, root, 1);
+
+        assert_eq!(
+            child_tweens(
+                &animation,
+                root,
+                tween(0, 1, 0.5),
+                &placements(&animation, root, 0)
+            ),
+            [tween(0, 1, 0.5), tween(0, 1, 0.5)]
+        );
+        assert_eq!(
+            child_tweens(&animation, root, tween(1, 0, 0.5), &second),
+            [tween(1, 0, 0.5), tween(1, 0, 0.5)]
+        );
+        assert_eq!(
+            child_tweens(&animation, root, tween(1, 2, 0.5), &second),
+            [tween(1, 1, 0.5), tween(1, 1, 0.5)]
+        );
+    }
+}
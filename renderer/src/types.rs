@@ -5,6 +5,7 @@ use hashbrown::HashMap;
 /// Based on the size of the data, the frames can be encoded in three formats:
 /// bytes, shorts (16-bit), or ints (32-bit).
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FrameData {
     /// Frame data is encoded as an array of bytes. Used when maximum value is < 255.
     Bytes(Vec<u8>),
@@ -19,6 +20,7 @@ pub enum FrameData {
 /// Sprite definitions can be encoded in several ways depending on the presence of
 /// multiple frames, actions, etc.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SpritePayload {
     /// Represents an indexed sprite with multiple frames.
     /// The payload contains: frame position array, frames duration, and action info array.
@@ -32,7 +34,8 @@ pub enum SpritePayload {
 }
 
 /// Actions that can be triggered during an animation.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Action {
     /// Add a particle system.
     /// Parameters: particle_id, offset_x, offset_y, offset_z.
@@ -55,13 +58,19 @@ pub enum Action {
     RunScript(String),
     /// Set the render radius.
     SetRadius(i8),
+    /// An action unknown to this crate, kept by lenient decoding with its parameters read as
+    /// strings, without their terminators.
+    Unknown { id: u8, raw_params: Vec<Vec<u8>> },
 }
 
 /// The root structure representing an Animation.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Animation {
     /// The version flags of the animation.
     pub version: AnimationVersion,
+    /// The header word stored right after the version flags.
+    pub header: i16,
     /// The frame rate of the animation.
     pub frame_rate: u8,
     /// Index data related to the animation, if any.
@@ -69,13 +78,29 @@ pub struct Animation {
     /// The texture associated with the animation.
     pub texture: Option<Texture>,
     /// The shapes defined in the animation, indexed by their ID.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "sorted::serialize"))]
     pub shapes: HashMap<i16, Shape>,
     /// Optional transform table defining rotations, translations, etc.
     pub transform: Option<TransformTable>,
     /// The sprites making up the animation, indexed by their ID.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "sorted::serialize"))]
     pub sprites: HashMap<i16, Sprite>,
     /// Imported assets.
     pub imports: Vec<Import>,
+    /// The IDs of the shapes in the order they were decoded, kept to encode them back the
+    /// same way. Shapes missing from it are encoded after the others, by ID.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub shape_order: Vec<i16>,
+    /// The IDs of the sprites in the order they were decoded, see
+    /// [`shape_order`](Self::shape_order).
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub sprite_order: Vec<i16>,
 }
 
 impl Animation {
@@ -88,9 +113,20 @@ impl Animation {
 
 /// A wrapper around a byte flag representing the animation version settings.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct AnimationVersion(pub u8);
 
 impl AnimationVersion {
+    /// All the flags with a known meaning.
+    pub const KNOWN_FLAGS: u8 = 0x1F;
+
+    /// Returns the bits that do not match any known flag.
+    #[inline]
+    pub fn unknown_flags(&self) -> u8 {
+        self.0 & !Self::KNOWN_FLAGS
+    }
+
     /// Check if the animation uses an atlas (`0x1` flag).
     pub fn use_atlas(&self) -> bool {
         self.0 & 0x1 == 0x1
@@ -119,6 +155,7 @@ impl AnimationVersion {
 
 /// Represents the index of an animation, containing metadata like scale and hideable parts.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AnimationIndex {
     /// The flags for the animation index.
     pub flags: AnimationFlags,
@@ -140,6 +177,8 @@ pub struct AnimationIndex {
 
 /// A wrapper around a byte flag defining features present in the Animation Index.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct AnimationFlags(pub u8);
 
 impl AnimationFlags {
@@ -186,6 +225,7 @@ impl AnimationFlags {
 
 /// Represents a record mapping an animation name to a file index.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AnimationFile {
     /// Name of the animation file.
     pub name: String,
@@ -197,6 +237,7 @@ pub struct AnimationFile {
 
 /// Represents a part that can be hidden by equipping a specific item.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct HiddenPart {
     /// Name of the item.
     pub item_name: String,
@@ -206,6 +247,7 @@ pub struct HiddenPart {
 
 /// Represents a part that hides another part when equipped.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct HideablePart {
     /// CRC key of the part.
     pub crc_key: i32,
@@ -215,8 +257,14 @@ pub struct HideablePart {
 
 /// Contains extended information like animation heights and highlight color.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AnimationExtension {
+    /// The raw flags word. Bits `0x1` and `0x2` follow the presence of `heights` and
+    /// `highlight_color`, the other bits are written back as they were read.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub flags: i32,
     /// Optional heights associated with animation names (stored by their CRC/hash).
+    #[cfg_attr(feature = "serde", serde(serialize_with = "sorted::serialize_opt"))]
     pub heights: Option<HashMap<i32, i8>>,
     /// Optional highlight color override.
     pub highlight_color: Option<Color>,
@@ -224,6 +272,7 @@ pub struct AnimationExtension {
 
 /// Represents an imported asset.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Import {
     /// Import ID.
     pub id: i16,
@@ -235,6 +284,7 @@ pub struct Import {
 
 /// Represents a texture definition within an animation.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Texture {
     /// Name of the texture.
     pub name: String,
@@ -244,6 +294,7 @@ pub struct Texture {
 
 /// Contains pre-computed arrays for transformations like colors, rotations, translations, and actions.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TransformTable {
     /// Array of float values representing color transformations.
     pub colors: Vec<f32>,
@@ -266,7 +317,8 @@ impl TransformTable {
 }
 
 /// Defines a 2D shape or sprite quad region within the texture atlas.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Shape {
     /// Unique ID of the shape.
     pub id: i16,
@@ -292,6 +344,7 @@ pub struct Shape {
 
 /// Represents a sprite definition, consisting of multiple frames, transformations, or nested sprites.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Sprite {
     /// Unique ID of the sprite.
     pub id: i16,
@@ -320,6 +373,8 @@ impl Sprite {
 
 /// A wrapper around a byte flag defining features for a Sprite.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct SpriteFlags(pub u8);
 
 impl SpriteFlags {
@@ -331,6 +386,7 @@ impl SpriteFlags {
 
 /// Contains name and CRC properties for a sprite.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SpriteName {
     /// Optional textual name of the sprite.
     pub name: Option<String>,
@@ -340,8 +396,17 @@ pub struct SpriteName {
     pub base_name_crc: i32,
 }
 
+/// Strips the leading direction from animation names like `1_AnimStatique`.
+pub(crate) fn strip_direction(name: &str) -> &str {
+    match name.split_once('_') {
+        Some((direction, base)) if direction.chars().all(|c| c.is_ascii_digit()) => base,
+        _ => name,
+    }
+}
+
 /// A standard RGBA color structure using floats.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Color {
     /// Red channel value (0.0 to 1.0).
     pub red: f32,
@@ -375,3 +440,35 @@ impl From<Color> for [f32; 4] {
         [color.red, color.green, color.blue, color.alpha]
     }
 }
+
+/// Serializers writing hash maps in key order, so that text output is stable.
+#[cfg(feature = "serde")]
+mod sorted {
+    use std::collections::BTreeMap;
+
+    use hashbrown::HashMap;
+    use serde::{Serialize, Serializer};
+
+    pub fn serialize<K, V, S>(map: &HashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        K: Ord + Serialize,
+        V: Serialize,
+        S: Serializer,
+    {
+        serializer.collect_map(map.iter().collect::<BTreeMap<_, _>>())
+    }
+
+    pub fn serialize_opt<K, V, S>(
+        map: &Option<HashMap<K, V>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        K: Ord + Serialize,
+        V: Serialize,
+        S: Serializer,
+    {
+        map.as_ref()
+            .map(|map| map.iter().collect::<BTreeMap<_, _>>())
+            .serialize(serializer)
+    }
+}